@@ -1,10 +1,13 @@
+use super::Slot;
 use crate::lexer::Token;
+use std::cell::Cell;
 
 #[derive(Debug, Clone)]
 pub enum Expr {
     Assign {
         name: Token,
         value: Box<Expr>,
+        slot: Cell<Slot>,
     },
     Binary {
         left: Box<Expr>,
@@ -16,6 +19,25 @@ pub enum Expr {
         paren: Token,
         arguments: Vec<Expr>,
     },
+    /// Null-coalescing `left ?? right`: `right` is evaluated only when `left`
+    /// is `nil`.
+    Coalesce {
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    /// `object.name op= value`, evaluating `object` once. `operator` is the
+    /// arithmetic operator, such as `+` for `+=`.
+    CompoundSet {
+        object: Box<Expr>,
+        name: Token,
+        operator: Token,
+        value: Box<Expr>,
+    },
+    Conditional {
+        condition: Box<Expr>,
+        then_branch: Box<Expr>,
+        else_branch: Box<Expr>,
+    },
     Get {
         object: Box<Expr>,
         name: Token,
@@ -36,6 +58,11 @@ pub enum Expr {
         name: Token,
         value: Box<Expr>,
     },
+    /// Converts the value of `expression` to a string; produced by string
+    /// interpolation.
+    Stringify {
+        expression: Box<Expr>,
+    },
     Super {
         keyword: Token,
         method: Token,
@@ -49,5 +76,27 @@ pub enum Expr {
     },
     Variable {
         name: Token,
+        slot: Cell<Slot>,
     },
 }
+
+impl Expr {
+    /// Source line of the leftmost token of the expression.
+    pub fn line(&self) -> usize {
+        match self {
+            Expr::Binary { left, .. }
+            | Expr::Coalesce { left, .. }
+            | Expr::Logical { left, .. } => left.line(),
+            Expr::Call { callee, .. } => callee.line(),
+            Expr::Conditional { condition, .. } => condition.line(),
+            Expr::Get { object, .. }
+            | Expr::Set { object, .. }
+            | Expr::CompoundSet { object, .. } => object.line(),
+            Expr::Grouping { expression } | Expr::Stringify { expression } => expression.line(),
+            Expr::Assign { name, .. } | Expr::Variable { name, .. } => name.line,
+            Expr::Literal { value } => value.line,
+            Expr::Super { keyword, .. } | Expr::This { keyword } => keyword.line,
+            Expr::Unary { operator, .. } => operator.line,
+        }
+    }
+}
@@ -1,7 +1,9 @@
 mod stmt;
 mod expr;
+mod resolution;
 mod visitor;
 
 pub use self::expr::Expr;
+pub use self::resolution::{FunctionLayout, Slot};
 pub use self::visitor::Visiter;
 pub use self::stmt::Stmt;
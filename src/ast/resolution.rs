@@ -0,0 +1,34 @@
+use crate::interner::Symbol;
+
+/// Where a variable lives at runtime.
+///
+/// The parser leaves every slot `Unresolved`; the tree-walking interpreter's
+/// resolver fills them in before the program runs. The bytecode compiler
+/// does its own resolution and ignores them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Slot {
+    #[default]
+    Unresolved,
+    /// Index into the interpreter's table of globals.
+    Global(usize),
+    /// Index into the slots of the current function's frame.
+    Local(usize),
+    /// A frame slot holding a cell shared with the closures capturing it.
+    /// Only declarations are marked this way; uses of the variable inside
+    /// its own function stay `Local`.
+    Captured(usize),
+    /// Index into the cells captured by the running closure.
+    Upvalue(usize),
+}
+
+/// How the frame of a function is laid out, computed by the resolver.
+#[derive(Debug, Default)]
+pub struct FunctionLayout {
+    /// Name of the variable stored in each frame slot.
+    pub names: Vec<Symbol>,
+    /// Slot of each parameter, `Local` or `Captured`.
+    pub params: Vec<Slot>,
+    /// Cells captured when the closure is created, as seen from the
+    /// enclosing function: `Captured(slot)` or `Upvalue(index)`.
+    pub upvalues: Vec<Slot>,
+}
@@ -1,6 +1,8 @@
 use crate::lexer::Token;
+use std::cell::{Cell, OnceCell};
+use std::rc::Rc;
 
-use super::Expr;
+use super::{Expr, FunctionLayout, Slot};
 
 #[derive(Debug, Clone)]
 pub enum Stmt {
@@ -19,6 +21,8 @@ pub enum Stmt {
         name: Token,
         params: Vec<Token>,
         body: Box<Stmt>,
+        slot: Cell<Slot>,
+        layout: OnceCell<Rc<FunctionLayout>>,
     },
     Print {
         expression: Expr,
@@ -26,6 +30,7 @@ pub enum Stmt {
     Var {
         name: Token,
         initializer: Option<Expr>,
+        slot: Cell<Slot>,
     },
     While {
         condition: Expr,
@@ -46,3 +51,23 @@ pub enum Stmt {
         else_branch: Option<Box<Stmt>>,
     },
 }
+
+impl Stmt {
+    /// Source line the statement starts on, if it contains any token.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Stmt::Block { statements } => statements.iter().find_map(Stmt::line),
+            Stmt::Class { name, .. } | Stmt::Function { name, .. } | Stmt::Var { name, .. } => {
+                Some(name.line)
+            }
+            Stmt::Expression { expression } | Stmt::Print { expression } => Some(expression.line()),
+            Stmt::While { condition, .. } | Stmt::If { condition, .. } => Some(condition.line()),
+            Stmt::For {
+                initializer,
+                condition,
+                ..
+            } => initializer.line().or(Some(condition.line())),
+            Stmt::Return { keyword, .. } => Some(keyword.line),
+        }
+    }
+}
@@ -1,69 +1,259 @@
 use crate::ast::*;
-use crate::lexer::Token;
+use crate::lexer::TokenKind;
 
+/// Prints the syntax tree as Lisp-style s-expressions, e.g. `(* (- 123) (group 48))`.
 pub struct AstPrinter;
 
 impl AstPrinter {
-    fn parenthesize(&self, operator: &str, exprs: &[&Expr]) -> String {
+    fn parenthesize(&mut self, operator: &str, exprs: &[&Expr]) -> String {
         let mut s = String::new();
-        s.push_str("(");
+        s.push('(');
         s.push_str(operator);
         for expr in exprs {
-            s.push_str(" ");
-            s.push_str(&self.visit_expr(*expr).as_str());
+            s.push(' ');
+            s.push_str(&self.visit_expr(expr));
         }
-        s.push_str(")");
+        s.push(')');
+        s
+    }
+
+    fn parenthesize_stmts(&mut self, operator: &str, stmts: &[Stmt]) -> String {
+        let mut s = String::new();
+        s.push('(');
+        s.push_str(operator);
+        for stmt in stmts {
+            s.push(' ');
+            s.push_str(&self.visit_stmt(stmt));
+        }
+        s.push(')');
         s
     }
 }
 
 impl Visiter for AstPrinter {
     type Expr = String;
-    fn visit_expr(&self, expr: &Expr) -> Self::Expr {
-        match *expr {
-            Expr::Assign {
-                ref name,
-                ref value,
-            } => self.parenthesize(&name.to_string(), &[&value]),
+    type Stmt = String;
+
+    fn visit_expr(&mut self, expr: &Expr) -> Self::Expr {
+        match expr {
+            Expr::Assign { name, value, .. } => self.parenthesize(&format!("= {}", name), &[value]),
             Expr::Binary {
-                ref left,
-                ref operator,
-                ref right,
-            } => self.parenthesize(&operator.to_string(), &[&left, &right]),
-
-            Expr::Grouping { ref expression } => self.parenthesize("group", &[&expression]),
-            Expr::Literal { ref value } => match value {
-                &Token::Nil => "nil".to_string(),
-                &Token::Number(n) => n.to_string(),
-                &Token::Str(ref s) => s.clone(),
+                left,
+                operator,
+                right,
+            }
+            | Expr::Logical {
+                left,
+                operator,
+                right,
+            } => self.parenthesize(&operator.to_string(), &[left, right]),
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                let mut exprs: Vec<&Expr> = vec![callee];
+                exprs.extend(arguments);
+                self.parenthesize("call", &exprs)
+            }
+            Expr::Coalesce { left, right } => self.parenthesize("??", &[left, right]),
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => self.parenthesize("?:", &[condition, then_branch, else_branch]),
+            Expr::Get { object, name } => self.parenthesize(&format!(". {}", name), &[object]),
+            Expr::Grouping { expression } => self.parenthesize("group", &[expression]),
+            Expr::Literal { value } => match &value.kind {
+                TokenKind::Str(s) => s.to_string(),
                 _ => value.to_string(),
             },
-            Expr::Unary {
-                ref operator,
-                ref right,
-            } => self.parenthesize(&operator.to_string(), &[&right]),
-            Expr::Variable { ref name } => name.to_string(),
-            _ => String::new(),
+            Expr::Set {
+                object,
+                name,
+                value,
+            } => self.parenthesize(&format!("= . {}", name), &[object, value]),
+            Expr::CompoundSet {
+                object,
+                name,
+                operator,
+                value,
+            } => self.parenthesize(&format!("{}= . {}", operator, name), &[object, value]),
+            Expr::Stringify { expression } => self.parenthesize("str", &[expression]),
+            Expr::Super { method, .. } => format!("(super {})", method),
+            Expr::This { .. } => "this".to_string(),
+            Expr::Unary { operator, right } => self.parenthesize(&operator.to_string(), &[right]),
+            Expr::Variable { name, .. } => name.to_string(),
+        }
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) -> Self::Stmt {
+        match stmt {
+            Stmt::Block { statements } => self.parenthesize_stmts("block", statements),
+            Stmt::Class { name, methods, .. } => {
+                self.parenthesize_stmts(&format!("class {}", name), methods)
+            }
+            Stmt::Expression { expression } => self.parenthesize(";", &[expression]),
+            Stmt::Function {
+                name, params, body, ..
+            } => {
+                let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+                format!(
+                    "(fun {} ({}) {})",
+                    name,
+                    params.join(" "),
+                    self.visit_stmt(body)
+                )
+            }
+            Stmt::Print { expression } => self.parenthesize("print", &[expression]),
+            Stmt::Var {
+                name, initializer, ..
+            } => match initializer {
+                Some(initializer) => self.parenthesize(&format!("var {}", name), &[initializer]),
+                None => format!("(var {})", name),
+            },
+            Stmt::While { condition, body } => {
+                format!(
+                    "(while {} {})",
+                    self.visit_expr(condition),
+                    self.visit_stmt(body)
+                )
+            }
+            Stmt::For {
+                initializer,
+                condition,
+                body,
+            } => format!(
+                "(for {} {} {})",
+                self.visit_stmt(initializer),
+                self.visit_expr(condition),
+                self.visit_stmt(body)
+            ),
+            Stmt::Return { value, .. } => match value {
+                Some(value) => self.parenthesize("return", &[value]),
+                None => "(return)".to_string(),
+            },
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let condition = self.visit_expr(condition);
+                let then_branch = self.visit_stmt(then_branch);
+                match else_branch {
+                    Some(else_branch) => format!(
+                        "(if {} {} {})",
+                        condition,
+                        then_branch,
+                        self.visit_stmt(else_branch)
+                    ),
+                    None => format!("(if {} {})", condition, then_branch),
+                }
+            }
         }
     }
 }
 
-#[test]
-fn test() {
-    let expr = Expr::Binary {
-        left: Box::new(Expr::Unary {
-            operator: Token::Minus,
-            right: Box::new(Expr::Literal {
-                value: Token::Number(123.0),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        lexer::{Lexer, Token},
+        parser::Parser,
+    };
+
+    fn print_expr(source: &str) -> String {
+        let tokens = Lexer::new(&format!("{};", source)).scan_tokens().unwrap();
+        let stmts = Parser::new(tokens, false).parse().unwrap();
+        match &stmts[0] {
+            Stmt::Expression { expression } => AstPrinter.visit_expr(expression),
+            _ => unreachable!(),
+        }
+    }
+
+    fn print_stmts(source: &str) -> String {
+        let tokens = Lexer::new(source).scan_tokens().unwrap();
+        let stmts = Parser::new(tokens, false).parse().unwrap();
+        let trees: Vec<String> = stmts
+            .iter()
+            .map(|stmt| AstPrinter.visit_stmt(stmt))
+            .collect();
+        trees.join(" ")
+    }
+
+    #[test]
+    fn test() {
+        let expr = Expr::Binary {
+            left: Box::new(Expr::Unary {
+                operator: Token::new(TokenKind::Minus, 1),
+                right: Box::new(Expr::Literal {
+                    value: Token::new(TokenKind::Number(123.0), 1),
+                }),
             }),
-        }),
-        operator: Token::Star,
-        right: Box::new(Expr::Grouping {
-            expression: Box::new(Expr::Literal {
-                value: Token::Number(48.0),
+            operator: Token::new(TokenKind::Star, 1),
+            right: Box::new(Expr::Grouping {
+                expression: Box::new(Expr::Literal {
+                    value: Token::new(TokenKind::Number(48.0), 1),
+                }),
             }),
-        }),
-    };
-    let printer = AstPrinter {};
-    println!("{}", printer.visit_expr(&expr));
+        };
+        assert_eq!(AstPrinter.visit_expr(&expr), "(* (- 123) (group 48))");
+    }
+
+    #[test]
+    fn conditional_is_right_associative() {
+        assert_eq!(print_expr("a ? b : c ? d : e"), "(?: a b (?: c d e))");
+        assert_eq!(print_expr("a ? b ? c : d : e"), "(?: a (?: b c d) e)");
+    }
+
+    #[test]
+    fn conditional_binds_looser_than_coalesce_and_or() {
+        assert_eq!(
+            print_expr("a ?? b ? c or d : e"),
+            "(?: (?? a b) (or c d) e)"
+        );
+        assert_eq!(print_expr("a or b ?? c"), "(?? (or a b) c)");
+        assert_eq!(print_expr("x = a ? b : c"), "(= x (?: a b c))");
+    }
+
+    #[test]
+    fn compound_field_assignment_keeps_one_object() {
+        assert_eq!(print_expr("f().x += 1"), "(+= . x (call f) 1)");
+        assert_eq!(print_expr("a.b.c -= 2"), "(-= . c (. b a) 2)");
+        assert_eq!(print_expr("a += 1"), "(= a (+ a 1))");
+    }
+
+    #[test]
+    fn else_binds_to_the_nearest_if() {
+        assert_eq!(
+            print_stmts("if a if b print 1; else print 2;"),
+            "(if a (if b (print 1) (print 2)))"
+        );
+        assert_eq!(
+            print_stmts("if a { if b print 1; } else print 2;"),
+            "(if a (block (if b (print 1))) (print 2))"
+        );
+    }
+
+    #[test]
+    fn else_if_chains() {
+        assert_eq!(
+            print_stmts("if a print 1; else if b print 2; else if c print 3; else print 4;"),
+            "(if a (print 1) (if b (print 2) (if c (print 3) (print 4))))"
+        );
+    }
+
+    #[test]
+    fn conditions_may_be_parenthesized() {
+        assert_eq!(
+            print_stmts("if (a) print 1; else print 2;"),
+            "(if (group a) (print 1) (print 2))"
+        );
+        assert_eq!(
+            print_stmts("while (a < 3) a = a + 1;"),
+            "(while (group (< a 3)) (; (= a (+ a 1))))"
+        );
+        assert_eq!(
+            print_stmts("for (var i = 0; i < 3; i = i + 1) print i;"),
+            print_stmts("for var i = 0; i < 3; i = i + 1 print i;")
+        );
+    }
 }
@@ -0,0 +1,211 @@
+use crate::cli::describe_io_error;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Instant;
+
+/// Backends every benchmark runs under, with the flags selecting them.
+pub const BACKENDS: [(&str, &[&str]); 2] = [("tree-walker", &[]), ("vm", &["--vm"])];
+
+/// Flag making the interpreter report its heap allocations on exit, as an
+/// `allocations: N` line on stderr. Only passed when built with the
+/// `count-allocations` feature.
+pub const COUNT_ALLOCATIONS: &str = "--count-allocations";
+
+/// Times each benchmark is run; the fastest run is reported.
+pub const RUNS: usize = 3;
+
+/// Name of the file in the benchmark directory holding the baseline timings.
+pub const BASELINE: &str = "baseline.txt";
+
+/// One benchmark run under one backend.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Measurement {
+    pub benchmark: String,
+    pub backend: String,
+    /// Wall time of the whole process in the fastest of [`RUNS`] runs, in
+    /// seconds.
+    pub seconds: f64,
+    /// Heap allocations of the run, if counted.
+    pub allocations: Option<u64>,
+}
+
+/// Timings of a previous run, by benchmark and backend.
+pub type Baseline = HashMap<(String, String), f64>;
+
+/// Every `.lox` file in `dir`, sorted by name.
+pub fn benchmarks(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| {
+        format!(
+            "Could not read benchmark directory '{}': {}.",
+            dir.display(),
+            describe_io_error(&e)
+        )
+    })?;
+    let mut benchmarks: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "lox"))
+        .collect();
+    benchmarks.sort();
+    Ok(benchmarks)
+}
+
+/// Run `benchmark` [`RUNS`] times with the interpreter at `exe` and the given
+/// backend flags. Fails if the program reports an error.
+pub fn measure(
+    exe: &Path,
+    benchmark: &Path,
+    backend: (&str, &[&str]),
+) -> Result<Measurement, String> {
+    let mut fastest = run_once(exe, benchmark, backend)?;
+    for _ in 1..RUNS {
+        let measurement = run_once(exe, benchmark, backend)?;
+        if measurement.seconds < fastest.seconds {
+            fastest = measurement;
+        }
+    }
+    Ok(fastest)
+}
+
+fn run_once(exe: &Path, benchmark: &Path, backend: (&str, &[&str])) -> Result<Measurement, String> {
+    let name = benchmark
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let start = Instant::now();
+    let mut command = Command::new(exe);
+    command.arg(benchmark).args(backend.1);
+    if cfg!(feature = "count-allocations") {
+        command.arg(COUNT_ALLOCATIONS);
+    }
+    let output = command
+        .output()
+        .map_err(|e| format!("Could not run '{}': {}", exe.display(), e))?;
+    let seconds = start.elapsed().as_secs_f64();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let mut allocations = None;
+    let mut errors = Vec::new();
+    for line in stderr.lines() {
+        match line.strip_prefix("allocations: ") {
+            Some(count) => allocations = count.parse().ok(),
+            None => errors.push(line),
+        }
+    }
+    if errors.is_empty() && output.status.success() {
+        Ok(Measurement {
+            benchmark: name,
+            backend: backend.0.to_string(),
+            seconds,
+            allocations,
+        })
+    } else {
+        Err(format!(
+            "Benchmark '{}' failed under {}: {}",
+            name,
+            backend.0,
+            errors.join("\n")
+        ))
+    }
+}
+
+/// Parse a baseline written by [`format_baseline`]. Blank lines and lines
+/// starting with `#` are ignored.
+pub fn parse_baseline(text: &str) -> Result<Baseline, String> {
+    let mut baseline = Baseline::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let seconds = match fields[..] {
+            [_, _, seconds] => seconds.parse::<f64>().ok(),
+            _ => None,
+        }
+        .ok_or_else(|| format!("Invalid baseline entry on line {}: '{}'", number + 1, line))?;
+        baseline.insert((fields[0].to_string(), fields[1].to_string()), seconds);
+    }
+    Ok(baseline)
+}
+
+pub fn format_baseline(measurements: &[Measurement]) -> String {
+    let mut out = String::from("# benchmark backend seconds\n");
+    for m in measurements {
+        writeln!(out, "{} {} {:.4}", m.benchmark, m.backend, m.seconds).unwrap();
+    }
+    out
+}
+
+/// A table of `measurements`, with each time relative to its baseline.
+/// Ratios above one are slowdowns.
+pub fn report(measurements: &[Measurement], baseline: &Baseline) -> String {
+    let mut out = format!(
+        "{:<16} {:<12} {:>9} {:>12} {:>12}\n",
+        "benchmark", "backend", "time", "allocations", "vs baseline"
+    );
+    for m in measurements {
+        let allocations = match m.allocations {
+            Some(allocations) => allocations.to_string(),
+            None => "-".to_string(),
+        };
+        let ratio = match baseline.get(&(m.benchmark.clone(), m.backend.clone())) {
+            Some(base) => format!("{:.2}x", m.seconds / base),
+            None => "-".to_string(),
+        };
+        writeln!(
+            out,
+            "{:<16} {:<12} {:>8.3}s {:>12} {:>12}",
+            m.benchmark, m.backend, m.seconds, allocations, ratio
+        )
+        .unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measurement(benchmark: &str, backend: &str, seconds: f64) -> Measurement {
+        Measurement {
+            benchmark: benchmark.to_string(),
+            backend: backend.to_string(),
+            seconds,
+            allocations: Some(42),
+        }
+    }
+
+    #[test]
+    fn baseline_round_trips() {
+        let measurements = [
+            measurement("fib", "vm", 0.5),
+            measurement("zoo", "tree-walker", 1.25),
+        ];
+        let baseline = parse_baseline(&format_baseline(&measurements)).unwrap();
+        assert_eq!(baseline.len(), 2);
+        assert_eq!(baseline[&("fib".to_string(), "vm".to_string())], 0.5);
+        assert_eq!(
+            baseline[&("zoo".to_string(), "tree-walker".to_string())],
+            1.25
+        );
+        assert!(parse_baseline("fib vm fast").is_err());
+    }
+
+    #[test]
+    fn report_compares_against_baseline() {
+        let baseline = parse_baseline("fib vm 0.5").unwrap();
+        let table = report(
+            &[measurement("fib", "vm", 1.0), measurement("zoo", "vm", 1.0)],
+            &baseline,
+        );
+        let rows: Vec<Vec<&str>> = table
+            .lines()
+            .skip(1)
+            .map(|line| line.split_whitespace().collect())
+            .collect();
+        assert_eq!(rows[0], ["fib", "vm", "1.000s", "42", "2.00x"]);
+        assert_eq!(rows[1], ["zoo", "vm", "1.000s", "42", "-"]);
+    }
+}
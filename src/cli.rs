@@ -0,0 +1,421 @@
+use std::io::{self, ErrorKind};
+
+/// Help text printed by `--help`.
+pub const USAGE: &str = "\
+Usage: lox-rs [OPTIONS] [COMMAND] [-- SCRIPT_ARGS...]
+
+Commands:
+  run <FILE>              Run a script (source or compiled .loxc); '-' reads stdin
+  repl                    Start the interactive prompt (the default)
+  check <FILE>            Report syntax errors without running; with --vm,
+                          the bytecode compiler's errors too
+  compile <FILE> [-o OUT] Write the script's bytecode to OUT (default FILE.loxc)
+  bench [DIR]             Time the programs in DIR (default ./bench) against
+                          their baseline; --save-baseline replaces it
+  <FILE>                  Same as `run <FILE>`
+
+Options:
+  -e <CODE>               Use CODE as the script instead of a file
+  --vm                    Run on the bytecode VM instead of the tree-walker
+  -O                      Optimize the syntax tree before running
+  --dump-bytecode         Print the compiled bytecode instead of running
+  --trace                 Log every statement or instruction to stderr
+  --trace-file=PATH       Write the trace to PATH instead
+  --trace-fn=NAME         Only trace inside functions called NAME
+  --stress-gc             Collect garbage on every allocation
+  --count-allocations     Report the number of heap allocations on exit
+                          (needs the count-allocations feature)
+  -h, --help              Print this help
+  -V, --version           Print the version
+
+Arguments after `--` are passed to the script, which reads them with
+`argCount()` and `arg(index)`.
+
+Exit status:
+  0   Success
+  64  The command line is invalid
+  65  The script has a syntax or compile error, or a compiled file is invalid
+  66  The script could not be read
+  70  The script failed at runtime
+  73  An output file could not be created
+  74  Some other I/O error
+";
+
+/// Process exit statuses, following the BSD `sysexits.h` convention.
+pub mod exit {
+    pub const USAGE: u8 = 64;
+    pub const DATA_ERR: u8 = 65;
+    pub const NO_INPUT: u8 = 66;
+    pub const SOFTWARE: u8 = 70;
+    pub const CANT_CREATE: u8 = 73;
+    pub const IO_ERR: u8 = 74;
+}
+
+/// An error ending the process, with the message to print and the exit
+/// status to report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    pub status: u8,
+    pub message: String,
+}
+
+impl Failure {
+    pub fn new(status: u8, message: impl Into<String>) -> Self {
+        Failure {
+            status,
+            message: message.into(),
+        }
+    }
+
+    /// A syntax or compile error in the script.
+    pub fn compile(message: impl Into<String>) -> Self {
+        Failure::new(exit::DATA_ERR, message)
+    }
+
+    /// An error raised while running the script.
+    pub fn runtime(message: impl Into<String>) -> Self {
+        Failure::new(exit::SOFTWARE, message)
+    }
+
+    /// Failing to read `what`, e.g. `'main.lox'`.
+    pub fn read(what: &str, error: &io::Error) -> Self {
+        let status = match error.kind() {
+            ErrorKind::NotFound | ErrorKind::PermissionDenied | ErrorKind::IsADirectory => {
+                exit::NO_INPUT
+            }
+            _ => exit::IO_ERR,
+        };
+        Failure::new(
+            status,
+            format!("Could not read {}: {}.", what, describe_io_error(error)),
+        )
+    }
+
+    /// Failing to create or write the file `what`.
+    pub fn write(what: &str, error: &io::Error) -> Self {
+        Failure::new(
+            exit::CANT_CREATE,
+            format!("Could not write {}: {}.", what, describe_io_error(error)),
+        )
+    }
+}
+
+/// `error` as a short lowercase phrase, without the OS error number that
+/// `io::Error` displays.
+pub fn describe_io_error(error: &io::Error) -> String {
+    match error.kind() {
+        ErrorKind::NotFound => "no such file or directory".to_string(),
+        ErrorKind::PermissionDenied => "permission denied".to_string(),
+        ErrorKind::IsADirectory => "it is a directory".to_string(),
+        ErrorKind::AlreadyExists => "it already exists".to_string(),
+        _ => {
+            let message = error.to_string();
+            let message = match message.find(" (os error") {
+                Some(end) => &message[..end],
+                None => &message,
+            };
+            let mut chars = message.chars();
+            match chars.next() {
+                Some(first) => first.to_lowercase().chain(chars).collect(),
+                None => message.to_string(),
+            }
+        }
+    }
+}
+
+/// Where the program to run comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Input {
+    File(String),
+    /// `-`: read the program from standard input.
+    Stdin,
+    /// `-e`: the program is given on the command line.
+    Inline(String),
+}
+
+impl Input {
+    fn from_arg(arg: &str) -> Self {
+        match arg {
+            "-" => Input::Stdin,
+            path => Input::File(path.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Run(Input),
+    Repl,
+    Check(Input),
+    Compile {
+        input: Input,
+        output: Option<String>,
+    },
+    Bench {
+        /// Directory holding the benchmarks and their baseline.
+        dir: String,
+        save_baseline: bool,
+    },
+    Help,
+    Version,
+}
+
+/// Parsed command line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cli {
+    pub command: Command,
+    pub vm: bool,
+    pub optimize: bool,
+    pub dump_bytecode: bool,
+    pub trace: bool,
+    pub trace_file: Option<String>,
+    pub trace_fn: Option<String>,
+    pub stress_gc: bool,
+    pub count_allocations: bool,
+    /// Everything after `--`, for the script's `arg(index)`.
+    pub script_args: Vec<String>,
+}
+
+impl Cli {
+    /// Parse the arguments following the program name.
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut cli = Cli {
+            command: Command::Repl,
+            vm: false,
+            optimize: false,
+            dump_bytecode: false,
+            trace: false,
+            trace_file: None,
+            trace_fn: None,
+            stress_gc: false,
+            count_allocations: false,
+            script_args: Vec::new(),
+        };
+        let mut positionals: Vec<&str> = Vec::new();
+        let mut inline = None;
+        let mut output = None;
+        let mut save_baseline = false;
+        let mut help = false;
+        let mut version = false;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = |option: &str| {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| format!("Option '{}' expects a value.", option))
+            };
+            match arg.as_str() {
+                "--" => {
+                    cli.script_args = args.cloned().collect();
+                    break;
+                }
+                "-e" => inline = Some(value("-e")?),
+                "-o" => output = Some(value("-o")?),
+                "-h" | "--help" => help = true,
+                "-V" | "--version" => version = true,
+                "--vm" => cli.vm = true,
+                "-O" => cli.optimize = true,
+                "--dump-bytecode" => cli.dump_bytecode = true,
+                "--trace" => cli.trace = true,
+                "--stress-gc" => cli.stress_gc = true,
+                "--count-allocations" if cfg!(feature = "count-allocations") => {
+                    cli.count_allocations = true
+                }
+                "--count-allocations" => {
+                    return Err(
+                        "Option '--count-allocations' needs the count-allocations feature."
+                            .to_string(),
+                    )
+                }
+                "--save-baseline" => save_baseline = true,
+                "-" => positionals.push(arg),
+                arg => {
+                    if let Some(path) = arg.strip_prefix("--trace-file=") {
+                        cli.trace_file = Some(path.to_string());
+                    } else if let Some(name) = arg.strip_prefix("--trace-fn=") {
+                        cli.trace_fn = Some(name.to_string());
+                    } else if arg.starts_with('-') {
+                        return Err(format!("Unknown option '{}'.", arg));
+                    } else {
+                        positionals.push(arg);
+                    }
+                }
+            }
+        }
+
+        if help {
+            cli.command = Command::Help;
+            return Ok(cli);
+        }
+        if version {
+            cli.command = Command::Version;
+            return Ok(cli);
+        }
+
+        // The script: from `-e`, or the one positional argument left.
+        let input = |rest: &[&str], command: &str| match (rest, &inline) {
+            ([], Some(code)) => Ok(Input::Inline(code.clone())),
+            ([], None) => Err(format!("'{}' expects a file, '-' or -e <CODE>.", command)),
+            ([path], None) => Ok(Input::from_arg(path)),
+            ([_], Some(_)) => Err("Cannot use both a file and -e.".to_string()),
+            ([_, extra, ..], _) => Err(format!("Unexpected argument '{}'.", extra)),
+        };
+        cli.command = match positionals.split_first() {
+            None if inline.is_some() => Command::Run(input(&[], "run")?),
+            None => Command::Repl,
+            Some((&"run", rest)) => Command::Run(input(rest, "run")?),
+            Some((&"check", rest)) => Command::Check(input(rest, "check")?),
+            Some((&"compile", rest)) => Command::Compile {
+                input: input(rest, "compile")?,
+                output: output.take(),
+            },
+            Some((&"repl", [])) => Command::Repl,
+            Some((&"bench", [])) => Command::Bench {
+                dir: "bench".to_string(),
+                save_baseline,
+            },
+            Some((&"bench", [dir])) => Command::Bench {
+                dir: dir.to_string(),
+                save_baseline,
+            },
+            Some((&"repl", [extra, ..]) | (&"bench", [_, extra, ..])) => {
+                return Err(format!("Unexpected argument '{}'.", extra))
+            }
+            Some(_) => Command::Run(input(&positionals, "run")?),
+        };
+        if output.is_some() {
+            return Err("Option '-o' is only valid with 'compile'.".to_string());
+        }
+        if save_baseline && !matches!(cli.command, Command::Bench { .. }) {
+            return Err("Option '--save-baseline' is only valid with 'bench'.".to_string());
+        }
+        Ok(cli)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Cli, String> {
+        let args: Vec<String> = args.split_whitespace().map(str::to_string).collect();
+        Cli::parse(&args)
+    }
+
+    fn command(args: &str) -> Command {
+        parse(args).unwrap().command
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(command(""), Command::Repl);
+        assert_eq!(command("repl --vm"), Command::Repl);
+        assert_eq!(
+            command("run a.lox"),
+            Command::Run(Input::File("a.lox".into()))
+        );
+        assert_eq!(command("a.lox"), Command::Run(Input::File("a.lox".into())));
+        assert_eq!(command("run -"), Command::Run(Input::Stdin));
+        assert_eq!(command("check -"), Command::Check(Input::Stdin));
+        assert_eq!(
+            command("-e print"),
+            Command::Run(Input::Inline("print".into()))
+        );
+        assert_eq!(
+            command("check -e print"),
+            Command::Check(Input::Inline("print".into()))
+        );
+        assert_eq!(
+            command("compile a.lox -o b.loxc"),
+            Command::Compile {
+                input: Input::File("a.lox".into()),
+                output: Some("b.loxc".into())
+            }
+        );
+        assert_eq!(
+            command("bench --save-baseline"),
+            Command::Bench {
+                dir: "bench".into(),
+                save_baseline: true
+            }
+        );
+        assert_eq!(
+            command("bench programs"),
+            Command::Bench {
+                dir: "programs".into(),
+                save_baseline: false
+            }
+        );
+        assert_eq!(command("run a.lox --help"), Command::Help);
+        assert_eq!(command("-V"), Command::Version);
+    }
+
+    #[test]
+    fn options_and_script_args() {
+        let cli = parse("--vm run -O a.lox --trace-fn=f --trace -- x -O --vm").unwrap();
+        assert!(cli.vm && cli.optimize && cli.trace);
+        assert_eq!(cli.trace_fn.as_deref(), Some("f"));
+        assert_eq!(cli.trace_file, None);
+        assert_eq!(cli.script_args, ["x", "-O", "--vm"]);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert_eq!(
+            parse("a.lox b.lox").unwrap_err(),
+            "Unexpected argument 'b.lox'."
+        );
+        assert_eq!(
+            parse("--fast a.lox").unwrap_err(),
+            "Unknown option '--fast'."
+        );
+        assert_eq!(
+            parse("run").unwrap_err(),
+            "'run' expects a file, '-' or -e <CODE>."
+        );
+        assert_eq!(
+            parse("a.lox -e x").unwrap_err(),
+            "Cannot use both a file and -e."
+        );
+        assert_eq!(parse("-e").unwrap_err(), "Option '-e' expects a value.");
+        assert_eq!(
+            parse("a.lox -o b").unwrap_err(),
+            "Option '-o' is only valid with 'compile'."
+        );
+        assert_eq!(
+            parse("repl a.lox").unwrap_err(),
+            "Unexpected argument 'a.lox'."
+        );
+        if !cfg!(feature = "count-allocations") {
+            assert_eq!(
+                parse("--count-allocations a.lox").unwrap_err(),
+                "Option '--count-allocations' needs the count-allocations feature."
+            );
+        }
+    }
+
+    #[test]
+    fn io_failures() {
+        let missing = Failure::read("'a.lox'", &io::Error::from(ErrorKind::NotFound));
+        assert_eq!(missing.status, exit::NO_INPUT);
+        assert_eq!(
+            missing.message,
+            "Could not read 'a.lox': no such file or directory."
+        );
+        let broken = Failure::read("standard input", &io::Error::other("Broken pipe"));
+        assert_eq!(broken.status, exit::IO_ERR);
+        assert_eq!(
+            broken.message,
+            "Could not read standard input: broken pipe."
+        );
+        let os = io::Error::from_raw_os_error(21);
+        assert!(!describe_io_error(&os).contains("os error"));
+        let denied = Failure::write("'a.loxc'", &io::Error::from(ErrorKind::PermissionDenied));
+        assert_eq!(denied.status, exit::CANT_CREATE);
+        assert_eq!(
+            denied.message,
+            "Could not write 'a.loxc': permission denied."
+        );
+    }
+}
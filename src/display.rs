@@ -0,0 +1,102 @@
+//! How values are shown, shared by both backends so they print the same.
+//!
+//! `print` and `"${...}"` use a value's `Display`; the REPL echoes its
+//! `repr`, which tells values of different types apart:
+//!
+//! | value        | `Display`          | `repr`                 |
+//! |--------------|--------------------|------------------------|
+//! | number       | `3`, `2.5`, `nan`, `inf`, `-inf` | the same |
+//! | string       | `a "b"`            | `"a \"b\""`            |
+//! | boolean, nil | `true`, `nil`      | the same               |
+//! | function     | `<fn fibo>`        | `<fn fibo/1>`          |
+//! | native       | `<native fn clock>`| `<native fn clock/0>`  |
+
+/// A number as Lox prints it: integral values without a fraction, and
+/// `nan`, `inf` and `-inf` for the non-finite ones.
+pub fn number(n: f64) -> String {
+    if n.is_nan() {
+        "nan".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else {
+        n.to_string()
+    }
+}
+
+pub fn function(name: &str) -> String {
+    format!("<fn {}>", name)
+}
+
+pub fn native(name: &str) -> String {
+    format!("<native fn {}>", name)
+}
+
+/// A string as a Lox literal that reads back as the same string.
+pub fn string_repr(s: &str) -> String {
+    let mut repr = String::from('"');
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => repr.push_str("\\\""),
+            '\\' => repr.push_str("\\\\"),
+            '\n' => repr.push_str("\\n"),
+            '\t' => repr.push_str("\\t"),
+            '\r' => repr.push_str("\\r"),
+            '\0' => repr.push_str("\\0"),
+            // `${` would start an interpolation.
+            '$' if chars.peek() == Some(&'{') => repr.push_str("\\$"),
+            c if c.is_control() => repr.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => repr.push(c),
+        }
+    }
+    repr.push('"');
+    repr
+}
+
+/// The `repr` of a callable: its `Display` form with the arity added, as
+/// `<fn fibo/1>`.
+pub fn callable_repr(display: &str, arity: usize) -> String {
+    match display.strip_suffix('>') {
+        Some(start) => format!("{}/{}>", start, arity),
+        None => display.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers() {
+        let shown: Vec<String> = [
+            3.0,
+            -0.0,
+            2.5,
+            1e-7,
+            f64::NAN,
+            f64::INFINITY,
+            -f64::INFINITY,
+        ]
+        .into_iter()
+        .map(number)
+        .collect();
+        assert_eq!(shown, ["3", "-0", "2.5", "0.0000001", "nan", "inf", "-inf"]);
+    }
+
+    #[test]
+    fn callables() {
+        assert_eq!(function("fibo"), "<fn fibo>");
+        assert_eq!(native("clock"), "<native fn clock>");
+        assert_eq!(callable_repr(&function("fibo"), 1), "<fn fibo/1>");
+        assert_eq!(callable_repr(&native("clock"), 0), "<native fn clock/0>");
+    }
+
+    #[test]
+    fn string_reprs_read_back_as_lox() {
+        assert_eq!(string_repr("plain"), "\"plain\"");
+        assert_eq!(
+            string_repr("say \"hi\"\n\\ $x ${y}\u{7}"),
+            "\"say \\\"hi\\\"\\n\\\\ $x \\${y}\\u{7}\""
+        );
+    }
+}
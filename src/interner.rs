@@ -0,0 +1,108 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt::{Debug, Display};
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::rc::Rc;
+
+thread_local! {
+    /// Every string interned so far. Entries live as long as the thread, which
+    /// is fine for identifiers and literals since they are bounded by the
+    /// size of the source code.
+    static STRINGS: RefCell<HashSet<Rc<str>>> = RefCell::new(HashSet::new());
+}
+
+/// An interned string: two symbols with the same text share one allocation,
+/// so cloning is a reference count bump and comparing or hashing only looks
+/// at the pointer.
+#[derive(Clone)]
+pub struct Symbol(Rc<str>);
+
+impl Symbol {
+    pub fn intern(s: &str) -> Self {
+        STRINGS.with(|strings| {
+            let mut strings = strings.borrow_mut();
+            match strings.get(s) {
+                Some(interned) => Symbol(Rc::clone(interned)),
+                None => {
+                    let interned: Rc<str> = s.into();
+                    strings.insert(Rc::clone(&interned));
+                    Symbol(interned)
+                }
+            }
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The shared string itself, for values that should keep pointing at the
+    /// interned copy.
+    pub fn as_rc(&self) -> &Rc<str> {
+        &self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (Rc::as_ptr(&self.0) as *const u8).hash(state);
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(s: &str) -> Self {
+        Symbol::intern(s)
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&*self.0, f)
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&*self.0, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_text_shares_one_allocation() {
+        let a = Symbol::intern("counter");
+        let b = Symbol::intern(&String::from("counter"));
+        assert!(Rc::ptr_eq(a.as_rc(), b.as_rc()));
+        assert_eq!(a, b);
+        assert_ne!(a, Symbol::intern("count"));
+    }
+
+    #[test]
+    fn behaves_like_its_text() {
+        let symbol = Symbol::intern("name");
+        assert_eq!(symbol.as_str(), "name");
+        assert_eq!(symbol.len(), 4);
+        assert_eq!(symbol.to_string(), "name");
+        assert_eq!(format!("{:?}", symbol), "\"name\"");
+    }
+}
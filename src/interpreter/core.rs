@@ -1,36 +1,98 @@
 use super::{
-    env::Environment,
     error::RuntimeError,
+    gc::{GcConfig, Heap},
+    native_functions::{Arg, ArgCount, Clock},
+    resolver::Resolver,
     types::{self, *},
 };
 use crate::{
-    ast::{Expr, Stmt, Visiter},
-    lexer::Token,
+    ast::{Expr, FunctionLayout, Slot, Stmt, Visiter},
+    ast_printer::AstPrinter,
+    interner::Symbol,
+    lexer::{Token, TokenKind},
+    trace::{self, Tracer},
+    vm::FRAMES_MAX,
 };
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::rc::Rc;
 
 pub struct Interpreter {
-    pub globals: Environment,
-    environment: Environment,
+    /// Values of the globals, indexed as in `global_names`. `None` until
+    /// the global is defined.
+    globals: Vec<Option<Type>>,
+    global_names: HashMap<Symbol, usize>,
+    heap: Heap,
+    tracer: Option<Tracer>,
+    /// Frames of the functions currently executing, with the top-level
+    /// script at the bottom.
+    frames: Vec<Frame>,
+}
+
+struct Frame {
+    name: Symbol,
+    layout: Rc<FunctionLayout>,
+    slots: Vec<Local>,
+    upvalues: Rc<[Upvalue]>,
+}
+
+/// A frame slot.
+#[derive(Clone)]
+enum Local {
+    Undefined,
+    Value(Type),
+    /// A variable captured by a closure.
+    Cell(Upvalue),
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
     pub fn new() -> Self {
-        let globals = Environment::global();
-        let environment = globals.clone();
-        Self {
-            globals,
-            environment,
-        }
+        Self::with_gc(GcConfig::default())
+    }
+
+    pub fn with_gc(config: GcConfig) -> Self {
+        let mut interpreter = Self {
+            globals: Vec::new(),
+            global_names: HashMap::new(),
+            heap: Heap::new(config),
+            tracer: None,
+            frames: vec![Frame::new(
+                trace::SCRIPT.into(),
+                Rc::default(),
+                Rc::from([]),
+            )],
+        };
+        interpreter.define_global("clock", Type::Callable(Rc::new(Clock {})));
+        interpreter.set_script_args(&[]);
+        interpreter
     }
+
+    /// Expose `args` to the program through the `argCount()` and
+    /// `arg(index)` natives.
+    pub fn set_script_args(&mut self, args: &[String]) {
+        let args: Rc<[Rc<str>]> = args.iter().map(|arg| arg.as_str().into()).collect();
+        let count = ArgCount { count: args.len() };
+        self.define_global("argCount", Type::Callable(Rc::new(count)));
+        self.define_global("arg", Type::Callable(Rc::new(Arg { args })));
+    }
+
+    /// Log every statement executed from now on to `tracer`.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
     pub fn interpret(&mut self, statements: &[Stmt]) -> Result<(), String> {
+        self.prepare_script(statements);
         for stmt in statements {
-            match self.visit_stmt(&stmt) {
+            match self.visit_stmt(stmt) {
                 Ok(_) => {}
-                Err(ReturnValue::Err(e)) => {
-                    // eprintln!("Panicked at {}", e.0);
-                    return Err(e);
-                }
+                Err(ReturnValue::Err(e)) => return Err(e.to_string()),
                 Err(ReturnValue::Return(_)) => {
                     return Err("Only functions can return values.".to_string());
                 }
@@ -39,27 +101,241 @@ impl Interpreter {
         Ok(())
     }
 
-    pub fn execute_block(
+    /// Evaluate `expression` at the top level, as the REPL does to echo it.
+    pub fn evaluate(&mut self, expression: &Expr) -> Result<Type, String> {
+        let statement = [Stmt::Expression {
+            expression: expression.clone(),
+        }];
+        self.prepare_script(&statement);
+        let [Stmt::Expression { expression }] = &statement else {
+            unreachable!()
+        };
+        self.visit_expr(expression).map_err(|e| e.to_string())
+    }
+
+    /// Resolve the variables of top-level `statements` and reset the script
+    /// frame to run them.
+    fn prepare_script(&mut self, statements: &[Stmt]) {
+        let layout = Resolver::resolve(&mut self.global_names, statements);
+        self.globals.resize(self.global_names.len(), None);
+        self.frames.truncate(1);
+        self.frames[0] = Frame::new(trace::SCRIPT.into(), Rc::new(layout), Rc::from([]));
+    }
+
+    /// The value of the global `name`, if it is defined.
+    pub fn global(&self, name: &str) -> Option<Type> {
+        let index = *self.global_names.get(&Symbol::intern(name))?;
+        self.globals.get(index).cloned().flatten()
+    }
+
+    /// Every defined global with its value, sorted by name.
+    pub fn globals(&self) -> Vec<(Symbol, Type)> {
+        sorted_globals(&self.global_names, &self.globals)
+    }
+
+    fn define_global(&mut self, name: &str, value: Type) {
+        let count = self.global_names.len();
+        let index = *self.global_names.entry(name.into()).or_insert(count);
+        self.globals.resize(self.global_names.len(), None);
+        self.globals[index] = Some(value);
+    }
+
+    /// Run `function` with `args` bound to its parameters, called at
+    /// `paren`.
+    pub fn execute_call(
         &mut self,
-        block: &[Stmt],
-        mut environment: Environment,
+        function: &types::Function,
+        paren: &Token,
+        args: Vec<Type>,
     ) -> Result<(), ReturnValue> {
-        std::mem::swap(&mut self.environment, &mut environment);
-        for stmt in block {
-            match self.visit_stmt(stmt) {
-                Ok(()) => Ok(()),
-                Err(value) => match value {
-                    ReturnValue::Err(e) => Err(ReturnValue::Err(e)),
-                    ReturnValue::Return(t) => {
-                        std::mem::swap(&mut self.environment, &mut environment);
-                        return Err(ReturnValue::Return(t));
-                    }
-                },
+        if self.frames.len() == FRAMES_MAX {
+            return Err(RuntimeError(paren.clone(), "Stack overflow.".to_string()).into());
+        }
+        let mut frame = Frame::new(
+            function.symbol(),
+            Rc::clone(&function.layout),
+            Rc::clone(&function.upvalues),
+        );
+        for (i, arg) in args.into_iter().enumerate() {
+            frame.slots[i] = match function.layout.params[i] {
+                Slot::Captured(_) => Local::Cell(self.new_upvalue(arg)),
+                _ => Local::Value(arg),
             };
         }
-        std::mem::swap(&mut self.environment, &mut environment);
+        self.frames.push(frame);
+        let result = match *function.body {
+            Stmt::Block { ref statements } => {
+                statements.iter().try_for_each(|stmt| self.visit_stmt(stmt))
+            }
+            ref body => self.visit_stmt(body),
+        };
+        self.frames.pop();
+        result
+    }
+
+    /// Allocate the cell of a captured variable, collecting garbage first if
+    /// enough has been allocated.
+    fn new_upvalue(&mut self, value: Type) -> Upvalue {
+        if self.heap.should_collect() {
+            self.collect_garbage();
+        }
+        let upvalue = Rc::new(RefCell::new(value));
+        self.heap.track_upvalue(&upvalue);
+        upvalue
+    }
+
+    /// Free unreachable closures and the cells they capture. Returns how
+    /// many cells were swept.
+    pub fn collect_garbage(&mut self) -> usize {
+        let frames = self.frames.iter().flat_map(|frame| {
+            frame.slots.iter().filter_map(|slot| match slot {
+                Local::Value(value) => Some(value),
+                _ => None,
+            })
+        });
+        self.heap
+            .collect(self.globals.iter().flatten().chain(frames))
+    }
+
+    /// Number of collections run so far.
+    pub fn gc_collections(&self) -> usize {
+        self.heap.collections()
+    }
+
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().unwrap()
+    }
+
+    fn get(&self, name: &Token, slot: Slot) -> Result<Type, RuntimeError> {
+        let frame = self.frames.last().unwrap();
+        let value = match slot {
+            Slot::Local(index) | Slot::Captured(index) => match &frame.slots[index] {
+                Local::Value(value) => Some(value.clone()),
+                Local::Cell(cell) => Some(cell.borrow().clone()),
+                Local::Undefined => None,
+            },
+            Slot::Upvalue(index) => Some(frame.upvalues[index].borrow().clone()),
+            Slot::Global(index) => self.globals[index].clone(),
+            Slot::Unresolved => None,
+        };
+        value.ok_or_else(|| undefined(name))
+    }
+
+    fn assign(&mut self, name: &Token, slot: Slot, value: Type) -> Result<(), RuntimeError> {
+        let frame = self.frames.last_mut().unwrap();
+        match slot {
+            Slot::Local(index) | Slot::Captured(index) => match &mut frame.slots[index] {
+                Local::Value(local) => *local = value,
+                Local::Cell(cell) => *cell.borrow_mut() = value,
+                Local::Undefined => return Err(undefined(name)),
+            },
+            Slot::Upvalue(index) => *frame.upvalues[index].borrow_mut() = value,
+            Slot::Global(index) => match &mut self.globals[index] {
+                Some(global) => *global = value,
+                None => return Err(undefined(name)),
+            },
+            Slot::Unresolved => return Err(undefined(name)),
+        }
         Ok(())
     }
+
+    /// Bind a newly declared variable.
+    fn define(&mut self, slot: Slot, value: Type) {
+        match slot {
+            Slot::Local(index) => self.frame().slots[index] = Local::Value(value),
+            Slot::Captured(index) => {
+                let upvalue = self.new_upvalue(value);
+                self.frame().slots[index] = Local::Cell(upvalue);
+            }
+            Slot::Global(index) => self.globals[index] = Some(value),
+            Slot::Upvalue(_) | Slot::Unresolved => unreachable!("Declarations are resolved"),
+        }
+    }
+
+    fn trace(&mut self, stmt: &Stmt) {
+        let Some(tracer) = &mut self.tracer else {
+            return;
+        };
+        let frame = self.frames.last().unwrap();
+        if matches!(stmt, Stmt::Block { .. }) || !tracer.traces(&frame.name) {
+            return;
+        }
+        // Compound statements are summarized; their bodies are traced as they run.
+        let event = match stmt {
+            Stmt::Class { name, .. } => format!("(class {})", name),
+            Stmt::Function { name, .. } => format!("(fun {})", name),
+            Stmt::If { condition, .. } => format!("(if {})", AstPrinter.visit_expr(condition)),
+            Stmt::While { condition, .. } | Stmt::For { condition, .. } => {
+                format!("(while {})", AstPrinter.visit_expr(condition))
+            }
+            _ => AstPrinter.visit_stmt(stmt),
+        };
+        let mut bindings: Vec<(Symbol, Type)> = frame
+            .layout
+            .names
+            .iter()
+            .zip(&frame.slots)
+            .filter_map(|(name, slot)| match slot {
+                Local::Value(value) => Some((name.clone(), value.clone())),
+                Local::Cell(cell) => Some((name.clone(), cell.borrow().clone())),
+                Local::Undefined => None,
+            })
+            .collect();
+        if self.frames.len() == 1 {
+            bindings.extend(sorted_globals(&self.global_names, &self.globals));
+        }
+        let bindings: Vec<String> = bindings
+            .into_iter()
+            .map(|(name, value)| match value {
+                Type::String(s) => format!("{} = {:?}", name, s),
+                Type::Callable(c) => format!("{} = {}", name, c),
+                value => format!("{} = {}", name, value),
+            })
+            .collect();
+        tracer.log(
+            stmt.line().unwrap_or_default(),
+            self.frames.len() - 1,
+            &frame.name,
+            &event,
+            &bindings.join(", "),
+        );
+    }
+}
+
+fn sorted_globals(names: &HashMap<Symbol, usize>, values: &[Option<Type>]) -> Vec<(Symbol, Type)> {
+    let mut globals: Vec<(Symbol, Type)> = names
+        .iter()
+        .filter_map(|(name, &index)| Some((name.clone(), values[index].clone()?)))
+        .collect();
+    globals.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+    globals
+}
+
+impl Frame {
+    fn new(name: Symbol, layout: Rc<FunctionLayout>, upvalues: Rc<[Upvalue]>) -> Self {
+        Self {
+            name,
+            slots: vec![Local::Undefined; layout.names.len()],
+            layout,
+            upvalues,
+        }
+    }
+}
+
+fn undefined(name: &Token) -> RuntimeError {
+    RuntimeError(name.clone(), format!("Undefined variable '{}'.", name))
+}
+
+/// Literals are interned, so equal ones share a pointer; strings built at
+/// runtime fall back to comparing their contents.
+fn same_string(s1: &Rc<str>, s2: &Rc<str>) -> bool {
+    Rc::ptr_eq(s1, s2) || s1 == s2
+}
+
+impl Drop for Interpreter {
+    fn drop(&mut self) {
+        self.heap.release();
+    }
 }
 
 impl Visiter for Interpreter {
@@ -67,19 +343,19 @@ impl Visiter for Interpreter {
     type Stmt = Result<(), ReturnValue>;
     fn visit_expr(&mut self, expr: &Expr) -> Self::Expr {
         match expr {
-            Expr::Literal { value } => match value {
-                Token::Number(n) => Ok(Type::Number(*n)),
-                Token::Str(s) => Ok(Type::String(s.to_string())),
-                Token::True => Ok(Type::Boolean(true)),
-                Token::False => Ok(Type::Boolean(false)),
-                Token::Nil => Ok(Type::Nil),
+            Expr::Literal { value } => match &value.kind {
+                TokenKind::Number(n) => Ok(Type::Number(*n)),
+                TokenKind::Str(s) => Ok(Type::String(Rc::clone(s.as_rc()))),
+                TokenKind::True => Ok(Type::Boolean(true)),
+                TokenKind::False => Ok(Type::Boolean(false)),
+                TokenKind::Nil => Ok(Type::Nil),
                 _ => Err(RuntimeError(value.clone(), "Unexpected token".to_string())),
             },
             Expr::Grouping { expression } => self.visit_expr(expression),
             Expr::Unary { operator, right } => {
                 let right = self.visit_expr(right)?;
-                match operator {
-                    Token::Minus => {
+                match operator.kind {
+                    TokenKind::Minus => {
                         if let Type::Number(n) = right {
                             Ok(Type::Number(-n))
                         } else {
@@ -89,7 +365,7 @@ impl Visiter for Interpreter {
                             ))
                         }
                     }
-                    Token::Bang => {
+                    TokenKind::Bang => {
                         if let Type::Boolean(b) = right {
                             Ok(Type::Boolean(!b))
                         } else {
@@ -105,12 +381,10 @@ impl Visiter for Interpreter {
                     )),
                 }
             }
-            Expr::Variable { name } => Ok(self.environment.get(name)?),
-            Expr::Assign { name, value } => {
-                let value = self.visit_expr(&value)?;
-                if let Err(e) = self.environment.assign(name.clone(), value.clone()) {
-                    return Err(e);
-                }
+            Expr::Variable { name, slot } => self.get(name, slot.get()),
+            Expr::Assign { name, value, slot } => {
+                let value = self.visit_expr(value)?;
+                self.assign(name, slot.get(), value.clone())?;
                 Ok(value)
             }
             Expr::Logical {
@@ -119,13 +393,13 @@ impl Visiter for Interpreter {
                 right,
             } => {
                 let left = self.visit_expr(left)?;
-                match operator {
-                    Token::Or => {
+                match operator.kind {
+                    TokenKind::Or => {
                         if left.is_true() {
                             return Ok(left);
                         }
                     }
-                    Token::And => {
+                    TokenKind::And => {
                         if !left.is_true() {
                             return Ok(left);
                         }
@@ -134,14 +408,29 @@ impl Visiter for Interpreter {
                 }
                 Ok(self.visit_expr(right)?)
             }
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                if self.visit_expr(condition)?.is_true() {
+                    self.visit_expr(then_branch)
+                } else {
+                    self.visit_expr(else_branch)
+                }
+            }
+            Expr::Coalesce { left, right } => match self.visit_expr(left)? {
+                Type::Nil => self.visit_expr(right),
+                left => Ok(left),
+            },
             Expr::Binary {
                 left,
                 operator,
                 right,
             } => {
                 let (left, right) = (self.visit_expr(left)?, self.visit_expr(right)?);
-                match operator {
-                    Token::Minus => {
+                match operator.kind {
+                    TokenKind::Minus => {
                         if let (Type::Number(n1), Type::Number(n2)) = (left, right) {
                             Ok(Type::Number(n1 - n2))
                         } else {
@@ -151,17 +440,17 @@ impl Visiter for Interpreter {
                             ))
                         }
                     }
-                    Token::Plus => match (left, right) {
+                    TokenKind::Plus => match (left, right) {
                         (Type::Number(n1), Type::Number(n2)) => Ok(Type::Number(n1 + n2)),
                         (Type::String(s1), Type::String(s2)) => {
-                            Ok(Type::String(format!("{}{}", s1, s2)))
+                            Ok(Type::String(format!("{}{}", s1, s2).into()))
                         }
                         _ => Err(RuntimeError(
                             operator.clone(),
                             "Operand must be both numbers or both strings.".to_string(),
                         )),
                     },
-                    Token::Slash => {
+                    TokenKind::Slash => {
                         if let (Type::Number(n1), Type::Number(n2)) = (left, right) {
                             Ok(Type::Number(n1 / n2))
                         } else {
@@ -171,7 +460,7 @@ impl Visiter for Interpreter {
                             ))
                         }
                     }
-                    Token::Star => {
+                    TokenKind::Star => {
                         if let (Type::Number(n1), Type::Number(n2)) = (left, right) {
                             Ok(Type::Number(n1 * n2))
                         } else {
@@ -181,7 +470,7 @@ impl Visiter for Interpreter {
                             ))
                         }
                     }
-                    Token::Mod => {
+                    TokenKind::Mod => {
                         if let (Type::Number(n1), Type::Number(n2)) = (left, right) {
                             Ok(Type::Number(n1 % n2))
                         } else {
@@ -191,7 +480,7 @@ impl Visiter for Interpreter {
                             ))
                         }
                     }
-                    Token::Greater => {
+                    TokenKind::Greater => {
                         if let (Type::Number(n1), Type::Number(n2)) = (left, right) {
                             Ok(Type::Boolean(n1 > n2))
                         } else {
@@ -201,7 +490,7 @@ impl Visiter for Interpreter {
                             ))
                         }
                     }
-                    Token::GreaterEqual => {
+                    TokenKind::GreaterEqual => {
                         if let (Type::Number(n1), Type::Number(n2)) = (left, right) {
                             Ok(Type::Boolean(n1 >= n2))
                         } else {
@@ -211,7 +500,7 @@ impl Visiter for Interpreter {
                             ))
                         }
                     }
-                    Token::Less => {
+                    TokenKind::Less => {
                         if let (Type::Number(n1), Type::Number(n2)) = (left, right) {
                             Ok(Type::Boolean(n1 < n2))
                         } else {
@@ -221,7 +510,7 @@ impl Visiter for Interpreter {
                             ))
                         }
                     }
-                    Token::LessEqual => {
+                    TokenKind::LessEqual => {
                         if let (Type::Number(n1), Type::Number(n2)) = (left, right) {
                             Ok(Type::Boolean(n1 <= n2))
                         } else {
@@ -231,16 +520,20 @@ impl Visiter for Interpreter {
                             ))
                         }
                     }
-                    Token::BangEqual => match (left, right) {
+                    TokenKind::BangEqual => match (left, right) {
                         (Type::Number(n1), Type::Number(n2)) => Ok(Type::Boolean(n1 != n2)),
-                        (Type::String(s1), Type::String(s2)) => Ok(Type::Boolean(s1 != s2)),
+                        (Type::String(s1), Type::String(s2)) => {
+                            Ok(Type::Boolean(!same_string(&s1, &s2)))
+                        }
                         (Type::Boolean(b1), Type::Boolean(b2)) => Ok(Type::Boolean(b1 != b2)),
                         (Type::Nil, Type::Nil) => Ok(Type::Boolean(false)),
                         _ => Ok(Type::Boolean(true)),
                     },
-                    Token::EqualEqual => match (left, right) {
+                    TokenKind::EqualEqual => match (left, right) {
                         (Type::Number(n1), Type::Number(n2)) => Ok(Type::Boolean(n1 == n2)),
-                        (Type::String(s1), Type::String(s2)) => Ok(Type::Boolean(s1 == s2)),
+                        (Type::String(s1), Type::String(s2)) => {
+                            Ok(Type::Boolean(same_string(&s1, &s2)))
+                        }
                         (Type::Boolean(b1), Type::Boolean(b2)) => Ok(Type::Boolean(b1 == b2)),
                         (Type::Nil, Type::Nil) => Ok(Type::Boolean(true)),
                         _ => Ok(Type::Boolean(false)),
@@ -252,6 +545,31 @@ impl Visiter for Interpreter {
                     )),
                 }
             }
+            Expr::Get { object, name } => {
+                self.visit_expr(object)?;
+                Err(RuntimeError(
+                    name.clone(),
+                    "Only instances have properties.".to_string(),
+                ))
+            }
+            // The field is read before it is written.
+            Expr::CompoundSet { object, name, .. } => {
+                self.visit_expr(object)?;
+                Err(RuntimeError(
+                    name.clone(),
+                    "Only instances have properties.".to_string(),
+                ))
+            }
+            Expr::Set { object, name, .. } => {
+                self.visit_expr(object)?;
+                Err(RuntimeError(
+                    name.clone(),
+                    "Only instances have fields.".to_string(),
+                ))
+            }
+            Expr::Stringify { expression } => Ok(Type::String(
+                self.visit_expr(expression)?.to_string().into(),
+            )),
             Expr::Call {
                 callee,
                 paren,
@@ -263,37 +581,82 @@ impl Visiter for Interpreter {
                     args.push(self.visit_expr(arg)?);
                 }
                 match callee {
-                    Type::Callable(func) => func.call(self, args),
+                    Type::Callable(func) => {
+                        if args.len() != func.arity() {
+                            return Err(RuntimeError(
+                                paren.clone(),
+                                format!(
+                                    "Expected {} arguments but got {}.",
+                                    func.arity(),
+                                    args.len()
+                                ),
+                            ));
+                        }
+                        func.call(self, paren, args)
+                    }
                     _ => Err(RuntimeError(
                         paren.clone(),
                         "Can only call functions and classes.".to_string(),
                     )),
                 }
             }
-            _ => Err(RuntimeError(Token::Eof, "Unexpected token.".to_string())),
+            Expr::Super { keyword, .. } | Expr::This { keyword } => Err(RuntimeError(
+                keyword.clone(),
+                format!("Can't use '{}' outside of a class.", keyword),
+            )),
         }
     }
 
     fn visit_stmt(&mut self, stmt: &Stmt) -> Self::Stmt {
+        if self.tracer.is_some() {
+            self.trace(stmt);
+        }
         match stmt {
             Stmt::Expression { expression } => {
                 self.visit_expr(expression)?;
                 Ok(())
             }
-            Stmt::Function { name, params, body } => {
-                let function = types::Function {
+            Stmt::Function {
+                name,
+                params,
+                body,
+                slot,
+                layout,
+            } => {
+                let slot = slot.get();
+                // A function referring to itself captures its own cell.
+                if let Slot::Captured(_) = slot {
+                    self.define(slot, Type::Nil);
+                }
+                let layout = Rc::clone(layout.get().expect("Functions are resolved"));
+                let frame = self.frames.last().unwrap();
+                let upvalues: Rc<[Upvalue]> = layout
+                    .upvalues
+                    .iter()
+                    .map(|capture| match *capture {
+                        Slot::Captured(index) => match &frame.slots[index] {
+                            Local::Cell(cell) => Rc::clone(cell),
+                            _ => unreachable!("Captured variables live in cells"),
+                        },
+                        Slot::Upvalue(index) => Rc::clone(&frame.upvalues[index]),
+                        _ => unreachable!("Closures capture locals or upvalues"),
+                    })
+                    .collect();
+                if self.heap.should_collect() {
+                    self.collect_garbage();
+                }
+                let function: Rc<dyn Callable> = Rc::new(types::Function {
                     name: name.clone(),
                     params: params.clone(),
                     body: body.clone(),
-                };
-                self.environment.define(
-                    if let Token::Identifier(ref n) = name {
-                        n
-                    } else {
-                        unreachable!()
-                    },
-                    Type::Callable(Rc::new(function)),
-                );
+                    layout,
+                    upvalues,
+                });
+                self.heap.track_function(&function);
+                match slot {
+                    Slot::Captured(_) => self.assign(name, slot, Type::Callable(function))?,
+                    _ => self.define(slot, Type::Callable(function)),
+                }
                 Ok(())
             }
             Stmt::Print { expression } => {
@@ -305,21 +668,21 @@ impl Visiter for Interpreter {
                 }
                 Ok(())
             }
-            Stmt::Return { keyword, value } => {
+            Stmt::Return { keyword: _, value } => {
                 if let Some(value) = value {
                     Err(ReturnValue::Return(self.visit_expr(value)?))
                 } else {
                     Err(ReturnValue::Return(Type::Nil))
                 }
             }
-            Stmt::Var { name, initializer } => {
+            Stmt::Var {
+                initializer, slot, ..
+            } => {
                 let mut value = Type::Nil;
                 if let Some(initializer) = initializer {
                     value = self.visit_expr(initializer)?;
                 }
-                if let Token::Identifier(name) = name {
-                    self.environment.define(name, value);
-                }
+                self.define(slot.get(), value);
                 Ok(())
             }
             Stmt::For {
@@ -327,14 +690,10 @@ impl Visiter for Interpreter {
                 condition,
                 body,
             } => {
-                let mut statements: Vec<Stmt> = Vec::new();
-                statements.push(*initializer.clone());
-                let while_loop = Stmt::While {
-                    condition: condition.clone(),
-                    body: body.clone(),
-                };
-                statements.push(while_loop);
-                self.visit_stmt(&Stmt::Block { statements })?;
+                self.visit_stmt(initializer)?;
+                while self.visit_expr(condition)?.is_true() {
+                    self.visit_stmt(body)?;
+                }
                 Ok(())
             }
             Stmt::While { condition, body } => {
@@ -344,8 +703,9 @@ impl Visiter for Interpreter {
                 Ok(())
             }
             Stmt::Block { statements } => {
-                let mut local = Environment::new(Some(&self.environment));
-                self.execute_block(statements, local)?;
+                for stmt in statements {
+                    self.visit_stmt(stmt)?;
+                }
                 Ok(())
             }
             Stmt::If {
@@ -353,22 +713,16 @@ impl Visiter for Interpreter {
                 then_branch,
                 else_branch,
             } => {
-                if let Ok(condition) = self.visit_expr(condition) {
-                    match condition {
-                        Type::Boolean(true) => {
-                            self.visit_stmt(&then_branch)?;
-                        }
-                        Type::Boolean(false) => {
-                            if let Some(else_branch) = else_branch {
-                                self.visit_stmt(else_branch)?;
-                            }
-                        }
-                        _ => unreachable!(),
-                    }
+                if self.visit_expr(condition)?.is_true() {
+                    self.visit_stmt(then_branch)?;
+                } else if let Some(else_branch) = else_branch {
+                    self.visit_stmt(else_branch)?;
                 }
                 Ok(())
             }
-            _ => Err(ReturnValue::Err("Unexpected token.".to_string())),
+            Stmt::Class { name, .. } => {
+                Err(RuntimeError(name.clone(), "Unexpected token.".to_string()).into())
+            }
         }
     }
 }
@@ -1,6 +1,14 @@
-use crate::lexer::Token;
 use crate::error::Error;
+use crate::lexer::Token;
+use std::fmt::Display;
 
 pub struct RuntimeError(pub Token, pub String);
 
 impl Error for RuntimeError {}
+
+/// The message, then the line it was raised on.
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}\n[line {}]", self.1, self.0.line)
+    }
+}
@@ -0,0 +1,262 @@
+use super::types::{Callable, Type, Upvalue};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+/// Tuning knobs for the interpreter's garbage collector.
+#[derive(Debug, Clone, Copy)]
+pub struct GcConfig {
+    /// Number of live objects at which the first collection runs.
+    pub initial_threshold: usize,
+    /// After a collection, the next one runs once the heap has grown to
+    /// `growth_factor` times the objects that survived.
+    pub growth_factor: usize,
+    /// Collect on every allocation. Slow, but flushes out objects that are
+    /// not reachable from the roots the collector knows about.
+    pub stress: bool,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        Self {
+            initial_threshold: 1024,
+            growth_factor: 2,
+            stress: false,
+        }
+    }
+}
+
+/// Tracing mark-and-sweep collector for closures and the variables they
+/// capture.
+///
+/// Objects stay reference counted, so anything the interpreter still holds
+/// on the Rust stack is kept alive. The heap only keeps weak references and
+/// exists to break the cycles reference counting cannot free, such as a
+/// function stored in a variable it captures.
+///
+/// Roots are the values passed to [`Heap::collect`] plus every object
+/// referenced from outside the heap, which are found by subtracting the
+/// references objects hold to each other from their strong counts. Sweeping
+/// a cell resets it to `nil`, dropping the edges of the cycle.
+pub struct Heap {
+    config: GcConfig,
+    upvalues: Vec<Weak<RefCell<Type>>>,
+    functions: Vec<Weak<dyn Callable>>,
+    next_gc: usize,
+    collections: usize,
+}
+
+/// Index of an object during a collection: cells first, then functions.
+type ObjectId = usize;
+
+impl Heap {
+    pub fn new(config: GcConfig) -> Self {
+        Self {
+            config,
+            upvalues: Vec::new(),
+            functions: Vec::new(),
+            next_gc: config.initial_threshold,
+            collections: 0,
+        }
+    }
+
+    pub fn track_upvalue(&mut self, upvalue: &Upvalue) {
+        self.upvalues.push(Rc::downgrade(upvalue));
+    }
+
+    pub fn track_function(&mut self, function: &Rc<dyn Callable>) {
+        self.functions.push(Rc::downgrade(function));
+    }
+
+    /// Whether enough has been allocated since the last collection.
+    pub fn should_collect(&self) -> bool {
+        self.config.stress || self.tracked() >= self.next_gc
+    }
+
+    /// Number of tracked objects, including ones already freed by reference
+    /// counting since the last collection.
+    pub fn tracked(&self) -> usize {
+        self.upvalues.len() + self.functions.len()
+    }
+
+    pub fn collections(&self) -> usize {
+        self.collections
+    }
+
+    /// Free every cycle not reachable from `roots` or from outside the heap.
+    /// Returns the number of cells swept.
+    pub fn collect<'a>(&mut self, roots: impl Iterator<Item = &'a Type>) -> usize {
+        self.collections += 1;
+        let upvalues: Vec<Upvalue> = self.upvalues.iter().filter_map(Weak::upgrade).collect();
+        let functions: Vec<Rc<dyn Callable>> =
+            self.functions.iter().filter_map(Weak::upgrade).collect();
+
+        let mut ids: HashMap<*const (), ObjectId> = HashMap::new();
+        for (id, upvalue) in upvalues.iter().enumerate() {
+            ids.insert(Rc::as_ptr(upvalue) as *const (), id);
+        }
+        for (id, function) in functions.iter().enumerate() {
+            ids.insert(Rc::as_ptr(function) as *const (), upvalues.len() + id);
+        }
+        let value_edge = |value: &Type| match value {
+            Type::Callable(callable) => ids.get(&(Rc::as_ptr(callable) as *const ())).copied(),
+            _ => None,
+        };
+        let edges = |id: ObjectId| -> Vec<ObjectId> {
+            if id < upvalues.len() {
+                value_edge(&upvalues[id].borrow()).into_iter().collect()
+            } else {
+                functions[id - upvalues.len()]
+                    .upvalues()
+                    .iter()
+                    .filter_map(|upvalue| ids.get(&(Rc::as_ptr(upvalue) as *const ())).copied())
+                    .collect()
+            }
+        };
+
+        // Whatever is left after discounting references from other tracked
+        // objects (and our own upgrade) is held from outside the heap.
+        let count = upvalues.len() + functions.len();
+        let mut external: Vec<isize> = upvalues
+            .iter()
+            .map(|upvalue| Rc::strong_count(upvalue) as isize - 1)
+            .chain(
+                functions
+                    .iter()
+                    .map(|function| Rc::strong_count(function) as isize - 1),
+            )
+            .collect();
+        for id in 0..count {
+            for target in edges(id) {
+                external[target] -= 1;
+            }
+        }
+
+        // Mark.
+        let mut marked = vec![false; count];
+        let mut gray: Vec<ObjectId> = (0..count).filter(|&id| external[id] > 0).collect();
+        gray.extend(roots.filter_map(value_edge));
+        while let Some(id) = gray.pop() {
+            if !marked[id] {
+                marked[id] = true;
+                gray.extend(edges(id));
+            }
+        }
+
+        // Sweep.
+        let mut swept = 0;
+        for (id, upvalue) in upvalues.iter().enumerate() {
+            if !marked[id] {
+                *upvalue.borrow_mut() = Type::Nil;
+                swept += 1;
+            }
+        }
+        self.upvalues = upvalues
+            .iter()
+            .zip(&marked)
+            .filter(|(_, &marked)| marked)
+            .map(|(upvalue, _)| Rc::downgrade(upvalue))
+            .collect();
+        self.functions = functions
+            .iter()
+            .zip(&marked[upvalues.len()..])
+            .filter(|(_, &marked)| marked)
+            .map(|(function, _)| Rc::downgrade(function))
+            .collect();
+        self.next_gc = self
+            .config
+            .initial_threshold
+            .max(self.tracked() * self.config.growth_factor);
+        swept
+    }
+
+    /// Break every cycle, reachable or not. Used when the interpreter goes away.
+    pub fn release(&mut self) {
+        for upvalue in self
+            .upvalues
+            .drain(..)
+            .filter_map(|upvalue| upvalue.upgrade())
+        {
+            *upvalue.borrow_mut() = Type::Nil;
+        }
+        self.functions.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn run(interpreter: &mut Interpreter, source: &str) {
+        let tokens = Lexer::new(source).scan_tokens().unwrap();
+        let stmts = Parser::new(tokens, false).parse().unwrap();
+        interpreter.interpret(&stmts).unwrap();
+    }
+
+    fn global(interpreter: &Interpreter, name: &str) -> String {
+        interpreter.global(name).unwrap().to_string()
+    }
+
+    #[test]
+    fn collects_self_referencing_closures() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "fun make() { fun f() { return f; } return f; }
+             for var i = 0; i < 100; i = i + 1 { make(); }",
+        );
+        // Every call to `make` left a closure stored in the variable it captures.
+        assert!(interpreter.collect_garbage() >= 100);
+        assert_eq!(interpreter.collect_garbage(), 0);
+    }
+
+    #[test]
+    fn keeps_reachable_closures() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "fun counter() { var count = 0; fun increment() { count = count + 1; return count; } return increment; }
+             var next = counter();
+             next();",
+        );
+        interpreter.collect_garbage();
+        run(&mut interpreter, "var result = next();");
+        assert_eq!(global(&interpreter, "result"), "2");
+    }
+
+    #[test]
+    fn stress_mode_collects_on_every_allocation() {
+        let mut interpreter = Interpreter::with_gc(GcConfig {
+            stress: true,
+            ..GcConfig::default()
+        });
+        run(
+            &mut interpreter,
+            "fun adder(a) { fun add(b) { return a + b; } return add; }
+             var total = 0;
+             for var i = 0; i < 10; i = i + 1 { total = total + adder(i)(adder(1)(i)); }",
+        );
+        assert_eq!(global(&interpreter, "total"), "100");
+        assert!(interpreter.gc_collections() >= 30);
+    }
+
+    #[test]
+    fn threshold_grows_with_surviving_objects() {
+        let mut interpreter = Interpreter::with_gc(GcConfig {
+            initial_threshold: 8,
+            growth_factor: 2,
+            stress: false,
+        });
+        run(
+            &mut interpreter,
+            "fun make() { fun f() { return f; } return f; }
+             for var i = 0; i < 100; i = i + 1 { make(); }",
+        );
+        let collections = interpreter.gc_collections();
+        assert!(collections > 0 && collections < 100);
+        assert!(interpreter.collect_garbage() < 8 * 2);
+    }
+}
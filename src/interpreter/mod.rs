@@ -1,9 +1,11 @@
 mod native_functions;
-mod env;
+mod gc;
 mod error;
+mod resolver;
 mod types;
 mod core;
 
 pub use error::RuntimeError;
+pub use gc::GcConfig;
 pub use types::Type;
 pub use self::core::Interpreter;
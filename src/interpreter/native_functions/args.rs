@@ -0,0 +1,62 @@
+use crate::display;
+use crate::interpreter::{types, Interpreter, RuntimeError, Type};
+use crate::lexer::Token;
+use std::{fmt::Display, rc::Rc};
+
+/// `argCount()`: how many arguments the script was given after `--`.
+#[derive(Debug)]
+pub struct ArgCount {
+    pub count: usize,
+}
+
+impl types::Callable for ArgCount {
+    fn arity(&self) -> usize {
+        0
+    }
+    fn call(
+        &self,
+        _interpreter: &mut Interpreter,
+        _paren: &Token,
+        _args: Vec<Type>,
+    ) -> Result<Type, RuntimeError> {
+        Ok(Type::Number(self.count as f64))
+    }
+}
+
+impl Display for ArgCount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", display::native("argCount"))
+    }
+}
+
+/// `arg(index)`: the script argument at `index`, or `nil` past the end.
+#[derive(Debug)]
+pub struct Arg {
+    pub args: Rc<[Rc<str>]>,
+}
+
+impl types::Callable for Arg {
+    fn arity(&self) -> usize {
+        1
+    }
+    fn call(
+        &self,
+        _interpreter: &mut Interpreter,
+        _paren: &Token,
+        args: Vec<Type>,
+    ) -> Result<Type, RuntimeError> {
+        let arg = match args[0] {
+            Type::Number(index) if index >= 0.0 && index.fract() == 0.0 => {
+                self.args.get(index as usize)
+            }
+            _ => None,
+        };
+        Ok(arg.map_or(Type::Nil, |arg| Type::String(Rc::clone(arg))))
+    }
+}
+
+impl Display for Arg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", display::native("arg"))
+    }
+}
@@ -1,5 +1,10 @@
-use crate::interpreter::{types, Interpreter, Type, RuntimeError};
-use std::{time::{SystemTime, UNIX_EPOCH}, fmt::Display};
+use crate::display;
+use crate::interpreter::{types, Interpreter, RuntimeError, Type};
+use crate::lexer::Token;
+use std::{
+    fmt::Display,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 #[derive(Debug)]
 pub struct Clock {}
@@ -8,7 +13,12 @@ impl types::Callable for Clock {
     fn arity(&self) -> usize {
         0
     }
-    fn call(&self, interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, RuntimeError> {
+    fn call(
+        &self,
+        _interpreter: &mut Interpreter,
+        _paren: &Token,
+        _args: Vec<Type>,
+    ) -> Result<Type, RuntimeError> {
         let time = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
@@ -19,6 +29,6 @@ impl types::Callable for Clock {
 
 impl Display for Clock {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "<native fn>")
+        write!(f, "{}", display::native("clock"))
     }
 }
@@ -1,3 +1,5 @@
+mod args;
 mod clock;
 
+pub use args::{Arg, ArgCount};
 pub use clock::Clock;
@@ -0,0 +1,378 @@
+use crate::ast::{Expr, FunctionLayout, Slot, Stmt};
+use crate::interner::Symbol;
+use crate::lexer::{Token, TokenKind};
+use std::cell::{Cell, OnceCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Static pass run before the tree-walker executes a program. Gives every
+/// local a slot in its function's frame, turns locals captured by inner
+/// functions into cells and numbers the globals, recording the result in
+/// the `slot` and `layout` fields of the syntax tree.
+pub struct Resolver<'a, 'ast> {
+    /// Index of every global name seen so far, shared across REPL lines.
+    globals: &'a mut HashMap<Symbol, usize>,
+    functions: Vec<FunctionScope<'ast>>,
+}
+
+#[derive(Default)]
+struct FunctionScope<'ast> {
+    layout: FunctionLayout,
+    /// Locals currently in scope, innermost last.
+    locals: Vec<Local<'ast>>,
+    scope_depth: usize,
+}
+
+struct Local<'ast> {
+    name: Symbol,
+    depth: usize,
+    slot: usize,
+    /// The declaring statement's slot, or `None` for a parameter.
+    declaration: Option<&'ast Cell<Slot>>,
+}
+
+impl<'a, 'ast> Resolver<'a, 'ast> {
+    /// Resolve a top-level program. Returns the layout of the frame holding
+    /// the locals of its top-level blocks.
+    pub fn resolve(
+        globals: &'a mut HashMap<Symbol, usize>,
+        statements: &'ast [Stmt],
+    ) -> FunctionLayout {
+        let mut resolver = Self {
+            globals,
+            functions: vec![FunctionScope::default()],
+        };
+        for stmt in statements {
+            resolver.statement(stmt);
+        }
+        resolver.functions.pop().unwrap().layout
+    }
+
+    fn statement(&mut self, stmt: &'ast Stmt) {
+        match stmt {
+            Stmt::Block { statements } => {
+                self.begin_scope();
+                for stmt in statements {
+                    self.statement(stmt);
+                }
+                self.end_scope();
+            }
+            Stmt::Class { methods, .. } => {
+                for method in methods {
+                    self.statement(method);
+                }
+            }
+            Stmt::Expression { expression } | Stmt::Print { expression } => {
+                self.expression(expression)
+            }
+            Stmt::Function {
+                name,
+                params,
+                body,
+                slot,
+                layout,
+            } => {
+                // Declare the name first so the body can refer to itself.
+                self.declare(name, slot);
+                self.function(params, body, layout);
+            }
+            Stmt::Var {
+                name,
+                initializer,
+                slot,
+            } => {
+                if let Some(initializer) = initializer {
+                    self.expression(initializer);
+                }
+                self.declare(name, slot);
+            }
+            Stmt::While { condition, body } => {
+                self.expression(condition);
+                self.statement(body);
+            }
+            Stmt::For {
+                initializer,
+                condition,
+                body,
+            } => {
+                self.begin_scope();
+                self.statement(initializer);
+                self.expression(condition);
+                self.statement(body);
+                self.end_scope();
+            }
+            Stmt::Return { value, .. } => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+        }
+    }
+
+    fn function(
+        &mut self,
+        params: &[Token],
+        body: &'ast Stmt,
+        layout: &'ast OnceCell<Rc<FunctionLayout>>,
+    ) {
+        self.functions.push(FunctionScope::default());
+        self.begin_scope();
+        for param in params {
+            let slot = self.add_local(param, None);
+            self.scope_mut().layout.params.push(Slot::Local(slot));
+        }
+        match body {
+            Stmt::Block { statements } => {
+                for stmt in statements {
+                    self.statement(stmt);
+                }
+            }
+            _ => self.statement(body),
+        }
+        let scope = self.functions.pop().unwrap();
+        // Resolving the same tree twice yields the same layout.
+        let _ = layout.set(Rc::new(scope.layout));
+    }
+
+    fn declare(&mut self, name: &Token, slot: &'ast Cell<Slot>) {
+        if self.scope().scope_depth == 0 {
+            slot.set(Slot::Global(self.global(name)));
+        } else {
+            let index = self.add_local(name, Some(slot));
+            slot.set(Slot::Local(index));
+        }
+    }
+
+    fn add_local(&mut self, name: &Token, declaration: Option<&'ast Cell<Slot>>) -> usize {
+        let name = symbol(name);
+        let scope = self.scope_mut();
+        let slot = scope.layout.names.len();
+        scope.layout.names.push(name.clone());
+        let depth = scope.scope_depth;
+        scope.locals.push(Local {
+            name,
+            depth,
+            slot,
+            declaration,
+        });
+        slot
+    }
+
+    fn variable(&mut self, name: &Token) -> Slot {
+        let top = self.functions.len() - 1;
+        let symbol = symbol(name);
+        if let Some(local) = self.find_local(top, &symbol) {
+            Slot::Local(local)
+        } else if let Some(index) = self.resolve_upvalue(top, &symbol) {
+            Slot::Upvalue(index)
+        } else {
+            Slot::Global(self.global(name))
+        }
+    }
+
+    fn find_local(&self, function: usize, name: &Symbol) -> Option<usize> {
+        self.functions[function]
+            .locals
+            .iter()
+            .rev()
+            .find(|local| local.name == *name)
+            .map(|local| local.slot)
+    }
+
+    fn resolve_upvalue(&mut self, function: usize, name: &Symbol) -> Option<usize> {
+        if function == 0 {
+            return None;
+        }
+        let enclosing = &mut self.functions[function - 1];
+        let captured = match enclosing.locals.iter().rev().find(|l| l.name == *name) {
+            Some(local) => {
+                match local.declaration {
+                    Some(declaration) => declaration.set(Slot::Captured(local.slot)),
+                    None => enclosing.layout.params[local.slot] = Slot::Captured(local.slot),
+                }
+                Slot::Captured(local.slot)
+            }
+            None => Slot::Upvalue(self.resolve_upvalue(function - 1, name)?),
+        };
+        let upvalues = &mut self.functions[function].layout.upvalues;
+        Some(match upvalues.iter().position(|u| *u == captured) {
+            Some(existing) => existing,
+            None => {
+                upvalues.push(captured);
+                upvalues.len() - 1
+            }
+        })
+    }
+
+    fn global(&mut self, name: &Token) -> usize {
+        let count = self.globals.len();
+        *self.globals.entry(symbol(name)).or_insert(count)
+    }
+
+    fn begin_scope(&mut self) {
+        self.scope_mut().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        let scope = self.scope_mut();
+        scope.scope_depth -= 1;
+        let depth = scope.scope_depth;
+        while scope.locals.last().is_some_and(|local| local.depth > depth) {
+            scope.locals.pop();
+        }
+    }
+
+    fn scope(&self) -> &FunctionScope<'ast> {
+        self.functions.last().unwrap()
+    }
+
+    fn scope_mut(&mut self) -> &mut FunctionScope<'ast> {
+        self.functions.last_mut().unwrap()
+    }
+
+    fn expression(&mut self, expr: &'ast Expr) {
+        match expr {
+            Expr::Assign { name, value, slot } => {
+                self.expression(value);
+                slot.set(self.variable(name));
+            }
+            Expr::Variable { name, slot } => slot.set(self.variable(name)),
+            Expr::Binary { left, right, .. }
+            | Expr::Coalesce { left, right }
+            | Expr::Logical { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(condition);
+                self.expression(then_branch);
+                self.expression(else_branch);
+            }
+            Expr::Get { object, .. } => self.expression(object),
+            Expr::Set { object, value, .. } | Expr::CompoundSet { object, value, .. } => {
+                self.expression(object);
+                self.expression(value);
+            }
+            Expr::Grouping { expression }
+            | Expr::Stringify { expression }
+            | Expr::Unary {
+                right: expression, ..
+            } => self.expression(expression),
+            Expr::Literal { .. } | Expr::Super { .. } | Expr::This { .. } => (),
+        }
+    }
+}
+
+fn symbol(name: &Token) -> Symbol {
+    match &name.kind {
+        TokenKind::Identifier(symbol) => symbol.clone(),
+        _ => unreachable!("Variable names are identifiers"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    fn parse(source: &str) -> Vec<Stmt> {
+        let tokens = Lexer::new(source).scan_tokens().unwrap();
+        Parser::new(tokens, false).parse().unwrap()
+    }
+
+    fn var_slot(stmt: &Stmt) -> Slot {
+        match stmt {
+            Stmt::Var { slot, .. } | Stmt::Function { slot, .. } => slot.get(),
+            _ => panic!("Expected a declaration"),
+        }
+    }
+
+    #[test]
+    fn globals_are_numbered_in_order_of_appearance() {
+        let mut globals = HashMap::new();
+        let stmts = parse("var a = 1; var b = a; print c;");
+        let layout = Resolver::resolve(&mut globals, &stmts);
+        assert_eq!(var_slot(&stmts[0]), Slot::Global(0));
+        assert_eq!(var_slot(&stmts[1]), Slot::Global(1));
+        assert_eq!(globals[&Symbol::intern("c")], 2);
+        assert!(layout.names.is_empty());
+    }
+
+    #[test]
+    fn block_locals_get_frame_slots() {
+        let mut globals = HashMap::new();
+        let stmts = parse("{ var a = 1; { var a = a; var b = a; } }");
+        let layout = Resolver::resolve(&mut globals, &stmts);
+        let Stmt::Block { statements } = &stmts[0] else {
+            panic!("Expected a block")
+        };
+        let Stmt::Block { statements: inner } = &statements[1] else {
+            panic!("Expected a block")
+        };
+        assert_eq!(var_slot(&statements[0]), Slot::Local(0));
+        assert_eq!(var_slot(&inner[0]), Slot::Local(1));
+        // The shadowing initializer reads the outer `a`.
+        let Stmt::Var {
+            initializer: Some(Expr::Variable { slot, .. }),
+            ..
+        } = &inner[0]
+        else {
+            panic!("Expected a variable initializer")
+        };
+        assert_eq!(slot.get(), Slot::Local(0));
+        assert_eq!(layout.names.len(), 3);
+        assert!(globals.is_empty());
+    }
+
+    #[test]
+    fn captured_variables_become_cells_and_upvalues() {
+        let mut globals = HashMap::new();
+        let stmts = parse(
+            "fun outer(x) {
+               var unused = 0;
+               var y = 1;
+               fun middle() { fun inner() { return x + y; } return inner; }
+               return middle;
+             }",
+        );
+        Resolver::resolve(&mut globals, &stmts);
+        let Stmt::Function { layout, body, .. } = &stmts[0] else {
+            panic!("Expected a function")
+        };
+        let outer = layout.get().unwrap();
+        assert_eq!(outer.params, vec![Slot::Captured(0)]);
+        let Stmt::Block { statements } = &**body else {
+            panic!("Expected a body")
+        };
+        assert_eq!(var_slot(&statements[0]), Slot::Local(1));
+        assert_eq!(var_slot(&statements[1]), Slot::Captured(2));
+        let Stmt::Function { layout, .. } = &statements[2] else {
+            panic!("Expected a function")
+        };
+        let middle = layout.get().unwrap();
+        assert_eq!(middle.upvalues, vec![Slot::Captured(0), Slot::Captured(2)]);
+    }
+}
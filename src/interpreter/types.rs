@@ -1,15 +1,17 @@
-use crate::ast::Stmt;
-use crate::lexer::Token;
+use crate::ast::{FunctionLayout, Stmt};
+use crate::display;
+use crate::interner::Symbol;
+use crate::lexer::{Token, TokenKind};
 
-use super::env::Environment;
 use super::{Interpreter, RuntimeError};
+use std::cell::RefCell;
 use std::fmt::{Debug, Display};
 use std::rc::Rc;
 
 #[derive(Debug, Clone)]
 pub enum Type {
     Number(f64),
-    String(String),
+    String(Rc<str>),
     Boolean(bool),
     Callable(Rc<dyn Callable>),
     // Class(Box<Class>),
@@ -26,15 +28,35 @@ impl Type {
             _ => false,
         }
     }
+
+    /// The name of the value's type, as shown by the REPL.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Number(_) => "number",
+            Self::String(_) => "string",
+            Self::Boolean(_) => "boolean",
+            Self::Callable(_) => "function",
+            Self::Nil => "nil",
+        }
+    }
+
+    /// The value as the REPL echoes it; see [`display`] for the format.
+    pub fn repr(&self) -> String {
+        match self {
+            Self::String(s) => display::string_repr(s),
+            Self::Callable(c) => display::callable_repr(&c.to_string(), c.arity()),
+            value => value.to_string(),
+        }
+    }
 }
 
 impl Display for Type {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Type::Number(n) => write!(f, "{}", n),
+            Type::Number(n) => write!(f, "{}", display::number(*n)),
             Type::String(s) => write!(f, "{}", s),
             Type::Boolean(b) => write!(f, "{}", b),
-            Type::Callable(c) => write!(f, "{:?}", c),
+            Type::Callable(c) => write!(f, "{}", c),
             Type::Nil => write!(f, "nil"),
         }
     }
@@ -42,14 +64,48 @@ impl Display for Type {
 
 pub trait Callable: Debug + Display {
     fn arity(&self) -> usize;
-    fn call(&self, interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, RuntimeError>;
+    /// Call with `args`, already checked against [`Callable::arity`].
+    /// `paren` closes the call's arguments, locating errors at the call.
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        paren: &Token,
+        args: Vec<Type>,
+    ) -> Result<Type, RuntimeError>;
+    /// The variables captured by the callable, traced by the garbage collector.
+    fn upvalues(&self) -> &[Upvalue] {
+        &[]
+    }
 }
 
-#[derive(Debug)]
+/// Shared cell holding a variable captured by a closure.
+pub type Upvalue = Rc<RefCell<Type>>;
+
 pub struct Function {
     pub name: Token,
     pub params: Vec<Token>,
     pub body: Box<Stmt>,
+    pub layout: Rc<FunctionLayout>,
+    pub upvalues: Rc<[Upvalue]>,
+}
+
+impl Function {
+    pub fn symbol(&self) -> Symbol {
+        match &self.name.kind {
+            TokenKind::Identifier(name) => name.clone(),
+            _ => unreachable!("Function names are identifiers"),
+        }
+    }
+}
+
+impl Debug for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Captured variables may hold the function itself.
+        f.debug_struct("Function")
+            .field("name", &self.name)
+            .field("params", &self.params)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Callable for Function {
@@ -57,49 +113,39 @@ impl Callable for Function {
         self.params.len()
     }
 
-    fn call(&self, interpreter: &mut Interpreter, args: Vec<Type>) -> Result<Type, RuntimeError> {
-        let local = Environment::new(Some(&interpreter.globals));
-        for (i, arg) in args.into_iter().enumerate() {
-            local.define(
-                if let Token::Identifier(ref name) = self.params[i] {
-                    name
-                } else {
-                    unreachable!()
-                },
-                arg,
-            );
-        }
-
-        match interpreter.execute_block(
-            if let Stmt::Block { ref statements } = *self.body {
-                statements
-            } else {
-                unreachable!()
-            },
-            local,
-        ) {
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        paren: &Token,
+        args: Vec<Type>,
+    ) -> Result<Type, RuntimeError> {
+        match interpreter.execute_call(self, paren, args) {
             Ok(()) => Ok(Type::Nil),
             Err(value) => match value {
-                ReturnValue::Err(e) => Err(RuntimeError(self.name.clone(), e)),
-                ReturnValue::Return(t) => Ok(t)
+                ReturnValue::Err(e) => Err(e),
+                ReturnValue::Return(t) => Ok(t),
             },
         }
     }
+
+    fn upvalues(&self) -> &[Upvalue] {
+        &self.upvalues
+    }
 }
 
 impl Display for Function {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "<fn {}>", self.name)
+        write!(f, "{}", display::function(&self.name.to_string()))
     }
 }
 
 pub enum ReturnValue {
-    Err(String),
+    Err(RuntimeError),
     Return(Type),
 }
 
 impl From<RuntimeError> for ReturnValue {
     fn from(value: RuntimeError) -> Self {
-        ReturnValue::Err(value.1)
+        ReturnValue::Err(value)
     }
 }
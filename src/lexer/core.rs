@@ -1,27 +1,270 @@
-use super::token::{Token::{self, *}, *};
+use super::token::{Token, TokenKind::{self, *}, *};
+use crate::interner::Symbol;
+use std::ops::Range;
 
 pub struct Lexer {
     source: Vec<char>,
+    /// Index of the first character of the token being scanned.
+    start: usize,
     current: usize,
+    line: usize,
+    errors: Vec<String>,
+    /// Unclosed `{` count for each `${` interpolation we are currently inside.
+    interpolations: Vec<usize>,
+    /// Set when the source ends inside a string, interpolation or block
+    /// comment.
+    incomplete: bool,
 }
 
 impl Lexer {
     pub fn new(source: &str) -> Self {
         Self {
             source: source.chars().collect(),
+            start: 0,
             current: 0,
+            line: 1,
+            errors: Vec::new(),
+            interpolations: Vec::new(),
+            incomplete: false,
         }
     }
 
-    pub fn scan_tokens(&mut self) -> Vec<Token> {
+    pub fn scan_tokens(&mut self) -> Result<Vec<Token>, String> {
         let mut tokens = vec![];
         while !self.at_end() {
             if let Some(token) = self.next() {
                 tokens.push(token);
             }
         }
-        tokens.push(Token::Eof);
-        tokens
+        if !self.interpolations.is_empty() {
+            self.incomplete = true;
+            self.error("Unterminated string interpolation.");
+        }
+        tokens.push(Token::new(Eof, self.line));
+
+        if self.errors.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(self.errors.join("\n"))
+        }
+    }
+
+    /// Scan every token with the range of characters it covers, for syntax
+    /// highlighting. Comments and malformed tokens are left out, and errors
+    /// are not reported.
+    pub fn scan_spans(&mut self) -> Vec<(TokenKind, Range<usize>)> {
+        let mut spans = Vec::new();
+        while !self.at_end() {
+            if let Some(kind) = self.scan_token() {
+                spans.push((kind, self.start..self.current));
+            }
+        }
+        spans
+    }
+
+    /// Whether the last [`scan_tokens`](Self::scan_tokens) ran out of source
+    /// inside a string, an interpolation or a block comment, so that more
+    /// input could still complete it.
+    pub fn is_incomplete(&self) -> bool {
+        self.incomplete
+    }
+
+    fn error(&mut self, message: &str) {
+        self.errors
+            .push(format!("[line {}] Error: {}", self.line, message));
+    }
+
+    /// Scan the rest of a string literal, up to the closing quote or the
+    /// start of an interpolated `${...}` expression.
+    fn string(&mut self) -> Option<TokenKind> {
+        let mut string = String::new();
+        loop {
+            match self.advance().copied() {
+                None => {
+                    self.incomplete = true;
+                    self.error("Unterminated string.");
+                    return None;
+                }
+                Some('"') => return Some(Str(Symbol::intern(&string))),
+                Some('\\') => {
+                    if let Some(c) = self.escape() {
+                        string.push(c);
+                    }
+                }
+                Some('$') if self.peek() == Some(&'{') => {
+                    self.advance();
+                    self.interpolations.push(0);
+                    return Some(Interpolation(Symbol::intern(&string)));
+                }
+                Some(c) => string.push(c),
+            }
+        }
+    }
+
+    /// Scan a number literal whose first digit has already been consumed.
+    ///
+    /// Accepts decimal literals with an optional fraction and exponent
+    /// (`1.5e-9`), hexadecimal (`0xFF`) and binary (`0b1010`) integers, and
+    /// `_` separators between digits (`1_000_000`). A `.` only starts a
+    /// fraction when a digit follows it, so `1.abs()` and `3.` lex the dot
+    /// separately.
+    fn number(&mut self, first: char) -> Option<TokenKind> {
+        let start = self.current - 1;
+        let radix = match (first, self.peek()) {
+            ('0', Some('x' | 'X')) => 16,
+            ('0', Some('b' | 'B')) => 2,
+            _ => 10,
+        };
+
+        let mut digits = String::new();
+        let mut valid = if radix == 10 {
+            digits.push(first);
+            self.digits(10, &mut digits)
+        } else {
+            self.advance();
+            let valid = self.digits(radix, &mut digits);
+            if valid && digits.is_empty() {
+                // Take the rest of a literal like `0xZZ` with it.
+                while self.peek().is_some_and(|&c| is_ident_continue(c)) {
+                    self.advance();
+                }
+                self.error("Expect digits after number prefix.");
+                return None;
+            }
+            valid
+        };
+
+        if radix == 10 {
+            if self.peek() == Some(&'.') && self.peek_next().is_some_and(char::is_ascii_digit) {
+                self.advance();
+                digits.push('.');
+                valid &= self.digits(10, &mut digits);
+            }
+            if let Some('e' | 'E') = self.peek() {
+                let signed = matches!(self.peek_next(), Some('+' | '-'));
+                let exponent_digit = self.source.get(self.current + 1 + signed as usize);
+                if exponent_digit.is_some_and(char::is_ascii_digit) {
+                    digits.push('e');
+                    self.advance();
+                    if signed {
+                        digits.push(*self.advance().expect("Sign was peeked"));
+                    }
+                    valid &= self.digits(10, &mut digits);
+                }
+            }
+        }
+
+        // A literal running straight into letters (`12abc`, `0b102`) is malformed.
+        if self.peek().is_some_and(|&c| is_ident_continue(c)) {
+            while self.peek().is_some_and(|&c| is_ident_continue(c)) {
+                self.advance();
+            }
+            let literal: String = self.source[start..self.current].iter().collect();
+            self.error(&format!("Invalid number literal '{}'.", literal));
+            return None;
+        }
+        if !valid {
+            return None;
+        }
+
+        // Decimals too large for an `f64` parse as infinity; reject those too.
+        let value = if radix == 10 {
+            digits.parse::<f64>().ok().filter(|n| n.is_finite())
+        } else {
+            u64::from_str_radix(&digits, radix).ok().map(|n| n as f64)
+        };
+        match value {
+            Some(n) => Some(Number(n)),
+            None => {
+                let literal: String = self.source[start..self.current].iter().collect();
+                self.error(&format!("Number literal '{}' is out of range.", literal));
+                None
+            }
+        }
+    }
+
+    /// Consume a run of `radix` digits into `digits`, skipping `_` separators.
+    /// Returns `false` if a separator is not placed between two digits.
+    fn digits(&mut self, radix: u32, digits: &mut String) -> bool {
+        let mut valid = true;
+        let mut after_digit = digits.chars().last().is_some_and(|c| c.is_digit(radix));
+        while let Some(&c) = self.peek() {
+            if c.is_ascii() && c.is_digit(radix) {
+                digits.push(c);
+                after_digit = true;
+            } else if c == '_' {
+                let before_digit = self
+                    .peek_next()
+                    .is_some_and(|c| c.is_ascii() && c.is_digit(radix));
+                if !after_digit || !before_digit {
+                    self.error("Digit separator '_' must be between digits.");
+                    valid = false;
+                }
+                after_digit = false;
+            } else {
+                break;
+            }
+            self.advance();
+        }
+        valid
+    }
+
+    /// Decode the escape sequence following a backslash.
+    fn escape(&mut self) -> Option<char> {
+        let c = match self.advance().copied() {
+            Some(c) => c,
+            None => {
+                self.incomplete = true;
+                self.error("Unterminated string.");
+                return None;
+            }
+        };
+        match c {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            '0' => Some('\0'),
+            '"' | '\\' | '$' | '\'' => Some(c),
+            'u' => self.unicode_escape(),
+            _ => {
+                self.error(&format!("Invalid escape sequence '\\{}'.", c));
+                None
+            }
+        }
+    }
+
+    /// Decode a `\u{...}` escape of one to six hex digits.
+    fn unicode_escape(&mut self) -> Option<char> {
+        if !self.match_advance('{') {
+            self.error("Expect '{' after '\\u'.");
+            return None;
+        }
+        let mut digits = String::new();
+        while let Some(&c) = self.peek() {
+            if c == '}' || c == '"' {
+                break;
+            }
+            digits.push(c);
+            self.advance();
+        }
+        if !self.match_advance('}') {
+            self.error("Unterminated unicode escape.");
+            return None;
+        }
+        if digits.is_empty() || digits.len() > 6 {
+            self.error("Unicode escape must have between 1 and 6 hex digits.");
+            return None;
+        }
+        match u32::from_str_radix(&digits, 16)
+            .ok()
+            .and_then(char::from_u32)
+        {
+            Some(c) => Some(c),
+            None => {
+                self.error(&format!("Invalid unicode escape '\\u{{{}}}'.", digits));
+                None
+            }
+        }
     }
 
     fn peek(&self) -> Option<&char> {
@@ -34,8 +277,11 @@ impl Lexer {
 
     fn advance(&mut self) -> Option<&char> {
         let c = self.source.get(self.current);
-        if c.is_some() {
-            self.current += 1
+        if let Some(c) = c {
+            self.current += 1;
+            if *c == '\n' {
+                self.line += 1;
+            }
         };
         c
     }
@@ -56,25 +302,81 @@ impl Lexer {
     }
 }
 
-impl Iterator for Lexer {
-    type Item = Token;
+/// Identifiers follow Unicode `XID_Start XID_Continue*`, with `_` also
+/// allowed as the first character.
+fn is_ident_start(c: char) -> bool {
+    c == '_' || unicode_ident::is_xid_start(c)
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let c = self.advance();
-        match c {
+fn is_ident_continue(c: char) -> bool {
+    unicode_ident::is_xid_continue(c)
+}
 
+impl Lexer {
+    fn scan_token(&mut self) -> Option<TokenKind> {
+        self.start = self.current;
+        let c = self.advance().copied();
+        match c {
             Some(c) => match c {
                 '(' => Some(LParen),
                 ')' => Some(RParen),
-                '{' => Some(LBrace),
-                '}' => Some(RBrace),
+                '{' => {
+                    if let Some(depth) = self.interpolations.last_mut() {
+                        *depth += 1;
+                    }
+                    Some(LBrace)
+                }
+                '}' => match self.interpolations.last_mut() {
+                    Some(0) => {
+                        // Closing brace of `${...}`: resume the enclosing string.
+                        self.interpolations.pop();
+                        self.string()
+                    }
+                    Some(depth) => {
+                        *depth -= 1;
+                        Some(RBrace)
+                    }
+                    None => Some(RBrace),
+                },
                 ',' => Some(Comma),
                 '.' => Some(Dot),
-                '-' => Some(Minus),
-                '+' => Some(Plus),
+                '-' => {
+                    if self.match_advance('=') {
+                        Some(MinusEqual)
+                    } else {
+                        Some(Minus)
+                    }
+                }
+                '+' => {
+                    if self.match_advance('=') {
+                        Some(PlusEqual)
+                    } else {
+                        Some(Plus)
+                    }
+                }
                 ';' => Some(Semicolon),
-                '*' => Some(Star),
-                '%' => Some(Mod),
+                ':' => Some(Colon),
+                '?' => {
+                    if self.match_advance('?') {
+                        Some(QuestionQuestion)
+                    } else {
+                        Some(Question)
+                    }
+                }
+                '*' => {
+                    if self.match_advance('=') {
+                        Some(StarEqual)
+                    } else {
+                        Some(Star)
+                    }
+                }
+                '%' => {
+                    if self.match_advance('=') {
+                        Some(ModEqual)
+                    } else {
+                        Some(Mod)
+                    }
+                }
                 '!' => {
                     if self.match_advance('=') {
                         Some(BangEqual)
@@ -109,49 +411,36 @@ impl Iterator for Lexer {
                         while self.peek()? != &'\n' && !self.at_end() {
                             self.advance();
                         }
-                        self.next()
+                        self.scan_token()
                     } else if self.match_advance('*') {
                         // Skip block comment
-                        while self.peek()? != &'*' || self.peek_next()? != &'/' {
+                        while self.peek() != Some(&'*') || self.peek_next() != Some(&'/') {
                             if self.at_end() {
-                                break;
+                                self.incomplete = true;
+                                self.error("Unterminated block comment.");
+                                return None;
                             }
                             self.advance();
                         }
                         self.advance();
                         self.advance();
-                        self.next()
+                        self.scan_token()
+                    } else if self.match_advance('=') {
+                        Some(SlashEqual)
                     } else {
                         Some(Slash)
                     }
                 }
-                '"' => {
-                    let mut string = String::new();
-                    while self.peek()? != &'"' && !self.at_end() {
-                        string.push(*self.advance().expect("Current char won't be None"));
-                    }
-                    self.advance();
-                    Some(Token::Str(string))
-                }
+                '"' => self.string(),
                 _ => {
-                    if c.is_numeric() {
-                        let mut number = String::new();
-                        number.push(*c);
-                        while let Some(c) = self.peek() {
-                            if c.is_numeric() || c == &'.' {
-                                number.push(*c);
-                                self.advance();
-                            } else {
-                                break;
-                            }
-                        }
-                        Some(Number(number.parse().unwrap()))
-                    } else if c.is_alphabetic() {
+                    if c.is_ascii_digit() {
+                        self.number(c)
+                    } else if is_ident_start(c) {
                         let mut identifier = String::new();
-                        identifier.push(*c);
-                        while let Some(c) = self.peek() {
-                            if c.is_alphanumeric() {
-                                identifier.push(*c);
+                        identifier.push(c);
+                        while let Some(&c) = self.peek() {
+                            if is_ident_continue(c) {
+                                identifier.push(c);
                                 self.advance();
                             } else {
                                 break;
@@ -159,13 +448,296 @@ impl Iterator for Lexer {
                         }
                         let token = keywords(&identifier);
                         Some(token)
-
+                    } else if c.is_whitespace() {
+                        None
                     } else {
+                        self.error(&format!("Unexpected character '{}'.", c));
                         None
                     }
                 }
             },
-            None => None
+            None => None,
         }
     }
 }
+
+impl Iterator for Lexer {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let kind = self.scan_token()?;
+        Some(Token::new(kind, self.line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan(source: &str) -> Vec<TokenKind> {
+        let tokens = Lexer::new(source).scan_tokens().expect("Source should lex");
+        tokens.into_iter().map(|t| t.kind).collect()
+    }
+
+    fn ident(name: &str) -> TokenKind {
+        Identifier(Symbol::intern(name))
+    }
+
+    fn str(s: &str) -> TokenKind {
+        Str(Symbol::intern(s))
+    }
+
+    fn errors(source: &str) -> String {
+        Lexer::new(source)
+            .scan_tokens()
+            .expect_err("Source should not lex")
+    }
+
+    #[test]
+    fn snake_case_and_leading_underscore() {
+        assert_eq!(
+            scan("my_var _private __dunder__ _ a1_b2"),
+            vec![
+                ident("my_var"),
+                ident("_private"),
+                ident("__dunder__"),
+                ident("_"),
+                ident("a1_b2"),
+                Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn unicode_identifiers() {
+        assert_eq!(
+            scan("var café = π; 变量 = naïve_größe;"),
+            vec![
+                Var,
+                ident("café"),
+                Equal,
+                ident("π"),
+                Semicolon,
+                ident("变量"),
+                Equal,
+                ident("naïve_größe"),
+                Semicolon,
+                Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn keywords_are_not_identifiers() {
+        assert_eq!(
+            scan("var variable _var"),
+            vec![Var, ident("variable"), ident("_var"), Eof]
+        );
+    }
+
+    #[test]
+    fn identifiers_cannot_start_with_digits_or_symbols() {
+        assert!(Lexer::new("1abc").scan_tokens().is_err());
+        assert!(Lexer::new("²x").scan_tokens().is_err());
+        assert!(Lexer::new("€uro").scan_tokens().is_err());
+    }
+
+    #[test]
+    fn tokens_record_their_line() {
+        let tokens = Lexer::new("var a\n= \"x\ny\";\n\nprint a;")
+            .scan_tokens()
+            .expect("Source should lex");
+        // A token spanning lines, like this string, records its last one.
+        let lines: Vec<usize> = tokens.iter().map(|t| t.line).collect();
+        assert_eq!(lines, [1, 1, 2, 3, 3, 5, 5, 5, 5]);
+    }
+
+    #[test]
+    fn spans_skip_whitespace_and_comments() {
+        let spans = Lexer::new("var s = /* c */ \"a${x}b\"; // done").scan_spans();
+        let ranges: Vec<Range<usize>> = spans.into_iter().map(|(_, range)| range).collect();
+        assert_eq!(ranges, [0..3, 4..5, 6..7, 16..20, 20..21, 21..24, 24..25]);
+    }
+
+    #[test]
+    fn incomplete_input() {
+        let incomplete = |source: &str| {
+            let mut lexer = Lexer::new(source);
+            let _ = lexer.scan_tokens();
+            lexer.is_incomplete()
+        };
+        assert!(incomplete("print \"abc"));
+        assert!(incomplete("print \"abc\\"));
+        assert!(incomplete("print \"${1 + "));
+        assert!(incomplete("/* comment"));
+        assert!(incomplete("/* comment *"));
+        assert!(!incomplete("print \"abc\"; /* done */"));
+        assert!(!incomplete("print 1 $;"));
+        assert!(!incomplete("fun f() {"));
+    }
+
+    #[test]
+    fn unterminated_block_comment() {
+        assert_eq!(
+            errors("print 1; /* never\nclosed *"),
+            "[line 2] Error: Unterminated block comment."
+        );
+        assert_eq!(scan("/* closed */"), [Eof]);
+    }
+
+    #[test]
+    fn combining_marks_continue_identifiers() {
+        // 'e' followed by U+0301 COMBINING ACUTE ACCENT.
+        assert_eq!(
+            scan("e\u{301}t\u{301}e\u{301}"),
+            vec![ident("e\u{301}t\u{301}e\u{301}"), Eof]
+        );
+    }
+
+    #[test]
+    fn number_literals() {
+        assert_eq!(scan("0xFF 0XfF"), [Number(255.0), Number(255.0), Eof]);
+        assert_eq!(scan("0b1010 0B1"), [Number(10.0), Number(1.0), Eof]);
+        assert_eq!(
+            scan("1_000_000 0xFF_FF"),
+            [Number(1e6), Number(65535.0), Eof]
+        );
+        assert_eq!(
+            scan("1e-9 1.5e+3 2E2"),
+            [Number(1e-9), Number(1500.0), Number(200.0), Eof]
+        );
+    }
+
+    #[test]
+    fn dots_after_numbers() {
+        assert_eq!(scan("3."), [Number(3.0), Dot, Eof]);
+        assert_eq!(scan("1..2"), [Number(1.0), Dot, Dot, Number(2.0), Eof]);
+        assert_eq!(scan("1.abs"), [Number(1.0), Dot, ident("abs"), Eof]);
+    }
+
+    #[test]
+    fn invalid_number_literals() {
+        let separator = "[line 1] Error: Digit separator '_' must be between digits.";
+        assert_eq!(errors("1_"), separator);
+        assert_eq!(errors("1__0"), [separator, separator].join("\n"));
+        assert_eq!(errors("0x_1"), separator);
+        assert_eq!(
+            errors("0x"),
+            "[line 1] Error: Expect digits after number prefix."
+        );
+        // The malformed rest of the literal is not lexed again.
+        assert_eq!(
+            errors("0xZZ + 1"),
+            "[line 1] Error: Expect digits after number prefix."
+        );
+        assert_eq!(
+            errors("0b102"),
+            "[line 1] Error: Invalid number literal '0b102'."
+        );
+        assert_eq!(
+            errors("12abc"),
+            "[line 1] Error: Invalid number literal '12abc'."
+        );
+        assert_eq!(
+            errors("1e400"),
+            "[line 1] Error: Number literal '1e400' is out of range."
+        );
+        assert_eq!(
+            errors("0x1_0000_0000_0000_0000"),
+            "[line 1] Error: Number literal '0x1_0000_0000_0000_0000' is out of range."
+        );
+    }
+
+    #[test]
+    fn escape_sequences() {
+        assert_eq!(scan(r#""a\nb""#), [str("a\nb"), Eof]);
+        assert_eq!(scan(r#""a\tb""#), [str("a\tb"), Eof]);
+        assert_eq!(scan(r#""a\rb\0""#), [str("a\rb\0"), Eof]);
+        assert_eq!(scan(r#""say \"hi\"""#), [str("say \"hi\""), Eof]);
+        assert_eq!(scan(r#""back\\slash""#), [str("back\\slash"), Eof]);
+        assert_eq!(scan(r#""\'\$""#), [str("'$"), Eof]);
+        assert_eq!(scan(r#""\${x}""#), [str("${x}"), Eof]);
+        assert_eq!(scan(r#""\u{41}\u{e9}\u{1F600}""#), [str("Aé😀"), Eof]);
+    }
+
+    #[test]
+    fn invalid_escapes() {
+        assert_eq!(
+            errors(r#""\q""#),
+            "[line 1] Error: Invalid escape sequence '\\q'."
+        );
+        assert_eq!(
+            errors(r#""\u41""#),
+            "[line 1] Error: Expect '{' after '\\u'."
+        );
+        assert_eq!(
+            errors(r#""\u{41""#),
+            "[line 1] Error: Unterminated unicode escape."
+        );
+        for digits in ["", "1234567"] {
+            assert_eq!(
+                errors(&format!(r#""\u{{{}}}""#, digits)),
+                "[line 1] Error: Unicode escape must have between 1 and 6 hex digits."
+            );
+        }
+        // Surrogates and values past U+10FFFF are not characters.
+        for digits in ["D800", "DFFF", "110000", "xyz"] {
+            assert_eq!(
+                errors(&format!(r#""\u{{{}}}""#, digits)),
+                format!(
+                    "[line 1] Error: Invalid unicode escape '\\u{{{}}}'.",
+                    digits
+                )
+            );
+        }
+        // Each bad escape is reported, and the string still ends at its quote.
+        assert_eq!(
+            errors("\"\\q\\u{D800}\"; \"\\w\";"),
+            "[line 1] Error: Invalid escape sequence '\\q'.\n\
+             [line 1] Error: Invalid unicode escape '\\u{D800}'.\n\
+             [line 1] Error: Invalid escape sequence '\\w'."
+        );
+    }
+
+    #[test]
+    fn interpolation() {
+        assert_eq!(
+            scan(r#""a ${x} b ${y}""#),
+            [
+                Interpolation(Symbol::intern("a ")),
+                ident("x"),
+                Interpolation(Symbol::intern(" b ")),
+                ident("y"),
+                str(""),
+                Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_interpolation() {
+        assert_eq!(
+            scan(r#""a${"b${1 + {}.c}" + "d"}e""#),
+            [
+                Interpolation(Symbol::intern("a")),
+                Interpolation(Symbol::intern("b")),
+                Number(1.0),
+                Plus,
+                LBrace,
+                RBrace,
+                Dot,
+                ident("c"),
+                str(""),
+                Plus,
+                str("d"),
+                str("e"),
+                Eof
+            ]
+        );
+        assert_eq!(
+            errors(r#""a${"b${1}"#),
+            "[line 1] Error: Unterminated string.\n\
+             [line 1] Error: Unterminated string interpolation."
+        );
+    }
+}
@@ -2,4 +2,4 @@ mod token;
 mod core;
 
 pub use self::core::Lexer;
-pub use self::token::Token;
+pub use self::token::{Token, TokenKind, KEYWORDS};
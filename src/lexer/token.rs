@@ -1,5 +1,26 @@
+use crate::interner::Symbol;
+
+/// A token together with the source line it ends on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub line: usize,
+}
+
+impl Token {
+    pub fn new(kind: TokenKind, line: usize) -> Self {
+        Self { kind, line }
+    }
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.kind.fmt(f)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
-pub enum Token {
+pub enum TokenKind {
     // Single-charactor tokens.
     LParen,
     RParen,
@@ -13,6 +34,7 @@ pub enum Token {
     Slash,
     Star,
     Mod,
+    Colon,
 
     // One or two charactor tokens.
     Bang,
@@ -23,10 +45,19 @@ pub enum Token {
     GreaterEqual,
     Less,
     LessEqual,
+    MinusEqual,
+    PlusEqual,
+    SlashEqual,
+    StarEqual,
+    ModEqual,
+    Question,
+    QuestionQuestion,
 
     // Literals.
-    Identifier(String),
-    Str(String),
+    Identifier(Symbol),
+    Str(Symbol),
+    /// String segment followed by an interpolated `${...}` expression.
+    Interpolation(Symbol),
     Number(f64),
 
     // Keywords.
@@ -50,14 +81,73 @@ pub enum Token {
     Eof,
 }
 
-impl std::fmt::Display for Token {
+impl std::fmt::Display for TokenKind {
+    /// Formats the token as it appears in source code.
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?} ", self)
+        use TokenKind::*;
+        let lexeme = match self {
+            LParen => "(",
+            RParen => ")",
+            LBrace => "{",
+            RBrace => "}",
+            Comma => ",",
+            Dot => ".",
+            Minus => "-",
+            Plus => "+",
+            Semicolon => ";",
+            Slash => "/",
+            Star => "*",
+            Mod => "%",
+            Colon => ":",
+            Bang => "!",
+            BangEqual => "!=",
+            Equal => "=",
+            EqualEqual => "==",
+            Greater => ">",
+            GreaterEqual => ">=",
+            Less => "<",
+            LessEqual => "<=",
+            MinusEqual => "-=",
+            PlusEqual => "+=",
+            SlashEqual => "/=",
+            StarEqual => "*=",
+            ModEqual => "%=",
+            Question => "?",
+            QuestionQuestion => "??",
+            Identifier(name) => name,
+            Str(s) => return write!(f, "{:?}", s),
+            Interpolation(s) => return write!(f, "{:?}${{", s),
+            Number(n) => return write!(f, "{}", n),
+            And => "and",
+            Class => "class",
+            Else => "else",
+            False => "false",
+            Fun => "fun",
+            For => "for",
+            If => "if",
+            Nil => "nil",
+            Or => "or",
+            Print => "print",
+            Return => "return",
+            Super => "super",
+            This => "this",
+            True => "true",
+            Var => "var",
+            While => "while",
+            Eof => "end of file",
+        };
+        write!(f, "{}", lexeme)
     }
 }
 
-pub fn keywords(s: &str) -> Token {
-    use Token::*;
+/// Every reserved word, as matched by [`keywords`].
+pub const KEYWORDS: [&str; 16] = [
+    "and", "class", "else", "false", "for", "fun", "if", "nil", "or", "print", "return", "super",
+    "this", "true", "var", "while",
+];
+
+pub fn keywords(s: &str) -> TokenKind {
+    use TokenKind::*;
     match s {
         "and" => And,
         "class" => Class,
@@ -75,6 +165,6 @@ pub fn keywords(s: &str) -> Token {
         "true" => True,
         "var" => Var,
         "while" => While,
-        _ => Identifier(s.to_string()),
+        _ => Identifier(Symbol::intern(s)),
     }
 }
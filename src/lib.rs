@@ -1,6 +1,14 @@
 pub mod error;
+pub mod interner;
 pub mod interpreter;
 pub mod ast;
-// mod ast_printer;
+pub mod ast_printer;
+pub mod bench;
+pub mod cli;
+pub mod display;
 pub mod lexer;
+pub mod optimizer;
 pub mod parser;
+pub mod repl;
+pub mod trace;
+pub mod vm;
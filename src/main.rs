@@ -1,34 +1,422 @@
-use lox_rs::{interpreter::*, lexer::*, parser::*};
+use lox_rs::{
+    ast::{Expr, Stmt, Visiter},
+    ast_printer::AstPrinter,
+    bench,
+    cli::{self, exit, Cli, Command, Failure, Input},
+    interpreter::*,
+    lexer::*,
+    optimizer,
+    parser::*,
+    repl::{self, LoxHelper, MetaCommand},
+    trace::Tracer,
+    vm::{self, Compiler, Vm},
+};
 
 use colored::Colorize;
-use rustyline::{error::ReadlineError, DefaultEditor};
-use std::{env, io::Result};
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() == 0 {
-        let _ = run_prompt();
-    } else {
-        let files: Vec<&String> = args.iter().filter(|arg| arg.ends_with("lox")).collect();
-        if files.len() == 0 {
-            let _ = run_prompt();
-        } else if files.len() == 1 {
-            let _ = run_file(files[0]);
+use rustyline::{error::ReadlineError, history::DefaultHistory, Editor};
+use std::{
+    env,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    thread,
+    time::Instant,
+};
+
+/// Built only with the `count-allocations` feature, so that normal runs use
+/// the system allocator.
+#[cfg(feature = "count-allocations")]
+mod counting {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    /// Counts heap allocations for `--count-allocations`.
+    struct CountingAllocator;
+
+    static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: CountingAllocator = CountingAllocator;
+
+    /// Heap allocations made so far.
+    pub fn allocations() -> u64 {
+        ALLOCATIONS.load(Ordering::Relaxed)
+    }
+}
+
+/// Stack of the thread running the command, deep enough for the tree-walker
+/// to reach [`vm::FRAMES_MAX`] nested calls and report a stack overflow.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let cli = match Cli::parse(&args) {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("{}\nRun 'lox-rs --help' for usage.", e);
+            return ExitCode::from(exit::USAGE);
+        }
+    };
+    let result = thread::scope(|scope| {
+        thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, || execute(&cli))
+            .expect("failed to spawn the interpreter thread")
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    });
+    #[cfg(feature = "count-allocations")]
+    if cli.count_allocations {
+        eprintln!("allocations: {}", counting::allocations());
+    }
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => {
+            eprintln!("{}", failure.message);
+            ExitCode::from(failure.status)
+        }
+    }
+}
+
+fn execute(cli: &Cli) -> std::result::Result<(), Failure> {
+    match &cli.command {
+        Command::Help => {
+            print!("{}", cli::USAGE);
+            Ok(())
+        }
+        Command::Version => {
+            println!("lox-rs {}", env!("CARGO_PKG_VERSION"));
+            Ok(())
+        }
+        Command::Bench { dir, save_baseline } => run_benchmarks(Path::new(dir), *save_baseline),
+        Command::Compile { input, output } => compile(input, output.as_deref(), cli.optimize),
+        Command::Check(input) => check(input, cli.vm),
+        Command::Repl => run_prompt(cli),
+        Command::Run(input) => run_input(input, cli),
+    }
+}
+
+/// A script to run: Lox source, or bytecode written by `compile`.
+enum Program {
+    Source(String),
+    Compiled(Vec<u8>),
+}
+
+fn read_program(input: &Input) -> std::result::Result<Program, Failure> {
+    let bytes = match input {
+        Input::Inline(code) => return Ok(Program::Source(code.clone())),
+        Input::File(path) => {
+            std::fs::read(path).map_err(|e| Failure::read(&format!("'{}'", path), &e))?
+        }
+        Input::Stdin => {
+            let mut bytes = Vec::new();
+            io::stdin()
+                .read_to_end(&mut bytes)
+                .map_err(|e| Failure::read("standard input", &e))?;
+            bytes
+        }
+    };
+    let named_compiled = matches!(input, Input::File(path) if path.ends_with(".loxc"));
+    if named_compiled || bytes.starts_with(&vm::MAGIC) {
+        return Ok(Program::Compiled(bytes));
+    }
+    String::from_utf8(bytes)
+        .map(Program::Source)
+        .map_err(|_| Failure::compile(format!("{} is not valid UTF-8.", describe(input))))
+}
+
+fn describe(input: &Input) -> String {
+    match input {
+        Input::File(path) => format!("'{}'", path),
+        Input::Stdin => "Standard input".to_string(),
+        Input::Inline(_) => "The -e code".to_string(),
+    }
+}
+
+fn load(input: &Input, bytes: &[u8]) -> std::result::Result<vm::Function, Failure> {
+    vm::deserialize(bytes)
+        .map_err(|e| Failure::compile(format!("Could not load {}: {}", describe(input), e)))
+}
+
+/// `run`: execute a script, or print its bytecode with `--dump-bytecode`.
+fn run_input(input: &Input, cli: &Cli) -> std::result::Result<(), Failure> {
+    let program = read_program(input)?;
+    if cli.dump_bytecode {
+        let function = match program {
+            Program::Compiled(bytes) => load(input, &bytes)?,
+            Program::Source(source) => {
+                let stmts = parse(&source, false, cli.optimize)?;
+                Compiler::compile(&stmts).map_err(Failure::compile)?
+            }
+        };
+        print!("{}", vm::disassemble(&function));
+        return Ok(());
+    }
+    match program {
+        Program::Source(source) => {
+            let mut backend = backend(cli, cli.vm)?;
+            run(&source, &mut backend, false, cli.optimize)
+        }
+        // Compiled files can only run on the VM.
+        Program::Compiled(bytes) => {
+            let function = load(input, &bytes)?;
+            let Backend::Vm(mut vm) = backend(cli, true)? else {
+                unreachable!("Compiled files run on the VM")
+            };
+            vm.run_function(function).map_err(Failure::runtime)
+        }
+    }
+}
+
+/// `check`: report the errors `run` would find before running anything,
+/// including the bytecode compiler's with `--vm`.
+fn check(input: &Input, vm: bool) -> std::result::Result<(), Failure> {
+    match read_program(input)? {
+        Program::Source(source) => {
+            let stmts = parse(&source, false, false)?;
+            if vm {
+                Compiler::compile(&stmts).map_err(Failure::compile)?;
+            }
+            Ok(())
+        }
+        Program::Compiled(bytes) => load(input, &bytes).map(|_| ()),
+    }
+}
+
+/// `compile`: write the bytecode of a script to a file that `run` executes
+/// without the source.
+fn compile(
+    input: &Input,
+    output: Option<&str>,
+    optimize: bool,
+) -> std::result::Result<(), Failure> {
+    let output = match (output, input) {
+        (Some(path), _) => PathBuf::from(path),
+        (None, Input::File(path)) => Path::new(path).with_extension("loxc"),
+        (None, _) => {
+            return Err(Failure::new(
+                exit::USAGE,
+                "Use -o to name the output when compiling stdin or -e.",
+            ))
+        }
+    };
+    let Program::Source(source) = read_program(input)? else {
+        return Err(Failure::compile(format!(
+            "{} is already compiled.",
+            describe(input)
+        )));
+    };
+    let stmts = parse(&source, false, optimize)?;
+    let function = Compiler::compile(&stmts).map_err(Failure::compile)?;
+    std::fs::write(&output, vm::serialize(&function))
+        .map_err(|e| Failure::write(&format!("'{}'", output.display()), &e))
+}
+
+/// Time every benchmark in `dir` under each backend and compare the results
+/// with the baseline stored next to them, replacing it if `save` is set.
+fn run_benchmarks(dir: &Path, save: bool) -> std::result::Result<(), Failure> {
+    let exe = env::current_exe().map_err(|e| Failure::new(exit::IO_ERR, e.to_string()))?;
+    let mut measurements = Vec::new();
+    for benchmark in bench::benchmarks(dir).map_err(|e| Failure::new(exit::NO_INPUT, e))? {
+        for backend in bench::BACKENDS {
+            let measurement =
+                bench::measure(&exe, &benchmark, backend).map_err(Failure::runtime)?;
+            measurements.push(measurement);
+        }
+    }
+    let baseline_path = dir.join(bench::BASELINE);
+    let baseline = match std::fs::read_to_string(&baseline_path) {
+        Ok(text) => bench::parse_baseline(&text).map_err(Failure::compile)?,
+        Err(_) => bench::Baseline::new(),
+    };
+    print!("{}", bench::report(&measurements, &baseline));
+    if save {
+        std::fs::write(&baseline_path, bench::format_baseline(&measurements))
+            .map_err(|e| Failure::write(&format!("'{}'", baseline_path.display()), &e))?;
+        println!("Saved baseline to {}", baseline_path.display());
+    }
+    Ok(())
+}
+
+/// Build the tracer requested with `--trace`, `--trace-file=PATH` and
+/// `--trace-fn=NAME`.
+fn tracer(cli: &Cli) -> std::result::Result<Option<Tracer>, Failure> {
+    let tracer = match &cli.trace_file {
+        Some(path) => {
+            Tracer::file(path).map_err(|e| Failure::write(&format!("trace file '{}'", path), &e))?
+        }
+        None if cli.trace => Tracer::stderr(),
+        None => return Ok(None),
+    };
+    Ok(Some(match &cli.trace_fn {
+        Some(name) => tracer.only_in(name),
+        None => tracer,
+    }))
+}
+
+/// The backend selected on the command line, with the script arguments
+/// given after `--`.
+fn backend(cli: &Cli, use_vm: bool) -> std::result::Result<Backend, Failure> {
+    let gc = GcConfig {
+        stress: cli.stress_gc,
+        ..GcConfig::default()
+    };
+    let mut backend = Backend::new(use_vm, tracer(cli)?, gc);
+    backend.set_script_args(&cli.script_args);
+    Ok(backend)
+}
+
+/// The engine executing parsed programs, selected with `--vm`.
+pub enum Backend {
+    TreeWalker(Interpreter),
+    Vm(Vm),
+}
+
+impl Backend {
+    pub fn new(use_vm: bool, tracer: Option<Tracer>, gc: GcConfig) -> Self {
+        if use_vm {
+            let mut vm = Vm::new();
+            vm.set_tracer(tracer);
+            Backend::Vm(vm)
         } else {
-            unreachable!("Please enter single file only!")
+            let mut interpreter = Interpreter::with_gc(gc);
+            interpreter.set_tracer(tracer);
+            Backend::TreeWalker(interpreter)
+        }
+    }
+
+    pub fn set_script_args(&mut self, args: &[String]) {
+        match self {
+            Backend::TreeWalker(interpreter) => interpreter.set_script_args(args),
+            Backend::Vm(vm) => vm.set_script_args(args),
+        }
+    }
+
+    /// Evaluate an expression typed at the prompt, and describe its value
+    /// and type for the prompt to echo, e.g. `=> 3 : number`.
+    pub fn evaluate(&mut self, expression: &Expr) -> std::result::Result<String, Failure> {
+        let (repr, type_name) = match self {
+            Backend::TreeWalker(interpreter) => {
+                let value = interpreter.evaluate(expression).map_err(Failure::runtime)?;
+                (value.repr(), value.type_name())
+            }
+            Backend::Vm(vm) => {
+                let function =
+                    Compiler::compile_expression(expression).map_err(Failure::compile)?;
+                let value = vm.evaluate_function(function).map_err(Failure::runtime)?;
+                (value.repr(), value.type_name())
+            }
+        };
+        Ok(match type_name {
+            "function" => format!("{} {}", "=>".dimmed(), repr.cyan()),
+            _ => format!(
+                "{} {} {}",
+                "=>".dimmed(),
+                repr.cyan(),
+                format!(": {}", type_name).dimmed()
+            ),
+        })
+    }
+
+    /// Every global with the `repr` of its value, as shown by `:env`.
+    pub fn globals(&self) -> Vec<(String, String)> {
+        match self {
+            Backend::TreeWalker(interpreter) => interpreter
+                .globals()
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.repr()))
+                .collect(),
+            Backend::Vm(vm) => vm
+                .globals()
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.repr()))
+                .collect(),
+        }
+    }
+
+    /// Run `statements`, telling compile errors from runtime errors.
+    pub fn interpret(&mut self, statements: &[Stmt]) -> std::result::Result<(), Failure> {
+        match self {
+            Backend::TreeWalker(interpreter) => {
+                interpreter.interpret(statements).map_err(Failure::runtime)
+            }
+            Backend::Vm(vm) => {
+                let function = Compiler::compile(statements).map_err(Failure::compile)?;
+                vm.run_function(function).map_err(Failure::runtime)
+            }
         }
     }
 }
 
-pub fn run_prompt() -> Result<()> {
-    let mut reader = DefaultEditor::new().unwrap();
-    let mut interpreter = Interpreter::new();
+/// The interactive prompt. Lines are collected until they form complete
+/// statements; lines starting with `:` are meta-commands (see
+/// [`repl::HELP`]).
+fn run_prompt(cli: &Cli) -> std::result::Result<(), Failure> {
+    let mut interpreter = backend(cli, cli.vm)?;
+    let mut reader: Editor<LoxHelper, DefaultHistory> = Editor::new().unwrap();
+    reader.set_helper(Some(LoxHelper::default()));
+    let history = repl::history_path();
+    if let Some(path) = &history {
+        // There is no history file before the first session.
+        let _ = reader.load_history(path);
+    }
+    // Lines of a statement that continues on the next line.
+    let mut pending = String::new();
     loop {
-        let line = reader.readline_with_initial("> ", ("", ""));
-        match line {
+        let prompt = if pending.is_empty() {
+            repl::PROMPT
+        } else {
+            repl::CONTINUATION_PROMPT
+        };
+        match reader.readline(prompt) {
             Ok(line) => {
-                run(&line, &mut interpreter, true);
+                let command = match pending.is_empty() {
+                    true => MetaCommand::parse(&line),
+                    false => None,
+                };
+                if let Some(command) = command {
+                    let _ = reader.add_history_entry(line.trim());
+                    let result = match command {
+                        Ok(MetaCommand::Quit) => break,
+                        Ok(command) => meta_command(command, &mut interpreter, cli),
+                        Err(e) => Err(Failure::new(exit::USAGE, e)),
+                    };
+                    if let Err(failure) = result {
+                        eprintln!("{}", failure.message);
+                    }
+                    complete_globals(&mut reader, &interpreter);
+                    continue;
+                }
+                pending.push_str(&line);
+                pending.push('\n');
+                if repl::is_incomplete(&pending) {
+                    continue;
+                }
+                let source = std::mem::take(&mut pending);
+                let _ = reader.add_history_entry(source.trim());
+                if let Err(failure) = run(&source, &mut interpreter, true, cli.optimize) {
+                    eprintln!("{}", failure.message);
+                }
+                complete_globals(&mut reader, &interpreter);
             }
             Err(ReadlineError::Interrupted) => {
+                pending.clear();
                 println!("{}", "CTRL-C".cyan().dimmed());
             }
             Err(ReadlineError::Eof) => break,
@@ -38,26 +426,109 @@ pub fn run_prompt() -> Result<()> {
             }
         }
     }
+    if let Some(path) = &history {
+        let saved = match path.parent() {
+            Some(dir) => std::fs::create_dir_all(dir).map_err(ReadlineError::from),
+            None => Ok(()),
+        }
+        .and_then(|()| reader.save_history(path));
+        if let Err(e) = saved {
+            eprintln!("Could not save history to '{}': {}", path.display(), e);
+        }
+    }
     Ok(())
 }
 
-pub fn run_file(path: &str) -> Result<()> {
-    let source = std::fs::read_to_string(path).unwrap();
-    let mut interpreter = Interpreter::new();
-    run(&source, &mut interpreter, false);
+/// Offer the globals defined so far as completions.
+fn complete_globals(reader: &mut Editor<LoxHelper, DefaultHistory>, interpreter: &Backend) {
+    if let Some(helper) = reader.helper_mut() {
+        helper.names = interpreter
+            .globals()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+    }
+}
+
+/// Run a meta-command other than `:quit`, which ends the prompt.
+fn meta_command(
+    command: MetaCommand,
+    interpreter: &mut Backend,
+    cli: &Cli,
+) -> std::result::Result<(), Failure> {
+    match command {
+        MetaCommand::Help => print!("{}", repl::HELP),
+        MetaCommand::Load(path) => {
+            let source = std::fs::read_to_string(&path)
+                .map_err(|e| Failure::read(&format!("'{}'", path), &e))?;
+            run(&source, interpreter, false, cli.optimize)?;
+        }
+        MetaCommand::Reset => *interpreter = backend(cli, cli.vm)?,
+        MetaCommand::Env => {
+            for (name, value) in interpreter.globals() {
+                println!("{} = {}", name, value);
+            }
+        }
+        MetaCommand::Ast(source) => println!("{}", syntax_tree(&source)?),
+        MetaCommand::Time(source) => {
+            let start = Instant::now();
+            let result = run(&source, interpreter, true, cli.optimize);
+            println!("{}", format!("took {:.3?}", start.elapsed()).dimmed());
+            result?;
+        }
+        MetaCommand::Quit => {}
+    }
     Ok(())
 }
 
-pub fn run(source: &str, interpreter: &mut Interpreter, is_repl: bool) {
-    let mut lexer = Lexer::new(source);
-    let tokens = lexer.scan_tokens();
-    let parser = Parser::new(tokens, is_repl);
-    let stmts = parser.parse();
+/// The syntax tree of `source` as printed by [`AstPrinter`]: of an
+/// expression if it is one, else of each statement.
+fn syntax_tree(source: &str) -> std::result::Result<String, Failure> {
+    let tokens = Lexer::new(source).scan_tokens().map_err(Failure::compile)?;
+    if let Ok(expr) = Parser::new(tokens.clone(), false).parse_expression() {
+        return Ok(AstPrinter.visit_expr(&expr));
+    }
+    let stmts = Parser::new(tokens, true)
+        .parse()
+        .map_err(Failure::compile)?;
+    let trees: Vec<String> = stmts
+        .iter()
+        .map(|stmt| AstPrinter.visit_stmt(stmt))
+        .collect();
+    Ok(trees.join("\n"))
+}
 
-    match stmts {
-        Ok(ref stmts) => {
-            interpreter.interpret(stmts);
+pub fn run(
+    source: &str,
+    interpreter: &mut Backend,
+    is_repl: bool,
+    optimize: bool,
+) -> std::result::Result<(), Failure> {
+    let stmts = parse(source, is_repl, optimize)?;
+    if !is_repl {
+        return interpreter.interpret(&stmts);
+    }
+    // The prompt echoes the value of every expression statement.
+    for stmt in &stmts {
+        match stmt {
+            Stmt::Expression { expression } => println!("{}", interpreter.evaluate(expression)?),
+            stmt => interpreter.interpret(std::slice::from_ref(stmt))?,
         }
-        Err(e) => eprintln!("{}", e),
-    };
+    }
+    Ok(())
+}
+
+/// Lex and parse `source`, then run the optimizer over it if `-O` was given.
+fn parse(source: &str, is_repl: bool, optimize: bool) -> std::result::Result<Vec<Stmt>, Failure> {
+    let tokens = Lexer::new(source)
+        .scan_tokens()
+        .map_err(|e| Failure::compile(e.to_string()))?;
+    let stmts = Parser::new(tokens, is_repl)
+        .parse()
+        .map_err(|e| Failure::compile(e.to_string()))?;
+    Ok(if optimize {
+        optimizer::optimize(stmts)
+    } else {
+        stmts
+    })
 }
@@ -0,0 +1,433 @@
+use crate::ast::{Expr, Stmt};
+use crate::lexer::{Token, TokenKind};
+
+/// Rewrite a parsed program into a cheaper one with the same behaviour,
+/// enabled with `-O`.
+///
+/// Operations on literals are folded into a single literal, branches whose
+/// condition is a literal are replaced by the branch that runs, and
+/// statements following a `return` are dropped. Anything that would fail at
+/// runtime, such as `1 + "a"`, is left alone so the error still happens
+/// where it used to.
+pub fn optimize(statements: Vec<Stmt>) -> Vec<Stmt> {
+    block(statements)
+}
+
+/// Optimize a list of statements, stopping after one that always returns.
+fn block(statements: Vec<Stmt>) -> Vec<Stmt> {
+    let mut optimized = Vec::with_capacity(statements.len());
+    for stmt in statements {
+        if let Some(stmt) = statement(stmt) {
+            let returns = always_returns(&stmt);
+            optimized.push(stmt);
+            if returns {
+                break;
+            }
+        }
+    }
+    optimized
+}
+
+/// Optimize one statement. Returns `None` if it can never do anything.
+fn statement(stmt: Stmt) -> Option<Stmt> {
+    Some(match stmt {
+        Stmt::Block { statements } => Stmt::Block {
+            statements: block(statements),
+        },
+        Stmt::Class {
+            name,
+            methods,
+            superclass,
+        } => Stmt::Class {
+            name,
+            methods: methods.into_iter().filter_map(statement).collect(),
+            superclass: superclass.map(expression),
+        },
+        Stmt::Expression { expression: expr } => Stmt::Expression {
+            expression: expression(expr),
+        },
+        Stmt::Function {
+            name,
+            params,
+            body,
+            slot,
+            layout,
+        } => Stmt::Function {
+            name,
+            params,
+            body: Box::new(statement(*body).unwrap_or(Stmt::Block { statements: vec![] })),
+            slot,
+            layout,
+        },
+        Stmt::Print { expression: expr } => Stmt::Print {
+            expression: expression(expr),
+        },
+        Stmt::Var {
+            name,
+            initializer,
+            slot,
+        } => Stmt::Var {
+            name,
+            initializer: initializer.map(expression),
+            slot,
+        },
+        Stmt::While { condition, body } => {
+            let condition = expression(condition);
+            if literal_truth(&condition) == Some(false) {
+                return None;
+            }
+            Stmt::While {
+                condition,
+                body: Box::new(statement(*body).unwrap_or(Stmt::Block { statements: vec![] })),
+            }
+        }
+        Stmt::For {
+            initializer,
+            condition,
+            body,
+        } => {
+            let initializer = statement(*initializer);
+            let condition = expression(condition);
+            if literal_truth(&condition) == Some(false) {
+                // The initializer still runs, in the loop's own scope.
+                return initializer.map(|initializer| Stmt::Block {
+                    statements: vec![initializer],
+                });
+            }
+            Stmt::For {
+                initializer: Box::new(initializer.unwrap_or(Stmt::Block { statements: vec![] })),
+                condition,
+                body: Box::new(statement(*body).unwrap_or(Stmt::Block { statements: vec![] })),
+            }
+        }
+        Stmt::Return { keyword, value } => Stmt::Return {
+            keyword,
+            value: value.map(expression),
+        },
+        Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            let condition = expression(condition);
+            match literal_truth(&condition) {
+                Some(true) => return statement(*then_branch),
+                Some(false) => return else_branch.and_then(|branch| statement(*branch)),
+                None => Stmt::If {
+                    condition,
+                    then_branch: Box::new(
+                        statement(*then_branch).unwrap_or(Stmt::Block { statements: vec![] }),
+                    ),
+                    else_branch: else_branch
+                        .and_then(|branch| statement(*branch))
+                        .map(Box::new),
+                },
+            }
+        }
+    })
+}
+
+/// Whether running `stmt` always ends in a `return`.
+fn always_returns(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Return { .. } => true,
+        Stmt::Block { statements } => statements.iter().any(always_returns),
+        Stmt::If {
+            then_branch,
+            else_branch: Some(else_branch),
+            ..
+        } => always_returns(then_branch) && always_returns(else_branch),
+        _ => false,
+    }
+}
+
+fn expression(expr: Expr) -> Expr {
+    match expr {
+        Expr::Assign { name, value, slot } => Expr::Assign {
+            name,
+            value: Box::new(expression(*value)),
+            slot,
+        },
+        Expr::Binary {
+            left,
+            operator,
+            right,
+        } => {
+            let (left, right) = (expression(*left), expression(*right));
+            let line = left.line();
+            match fold_binary(&left, &operator.kind, &right) {
+                Some(kind) => literal(kind, line),
+                None => Expr::Binary {
+                    left: Box::new(left),
+                    operator,
+                    right: Box::new(right),
+                },
+            }
+        }
+        Expr::Call {
+            callee,
+            paren,
+            arguments,
+        } => Expr::Call {
+            callee: Box::new(expression(*callee)),
+            paren,
+            arguments: arguments.into_iter().map(expression).collect(),
+        },
+        Expr::Coalesce { left, right } => {
+            let left = expression(*left);
+            match literal_kind(&left) {
+                Some(TokenKind::Nil) => expression(*right),
+                Some(_) => left,
+                None => Expr::Coalesce {
+                    left: Box::new(left),
+                    right: Box::new(expression(*right)),
+                },
+            }
+        }
+        Expr::Conditional {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            let condition = expression(*condition);
+            match literal_truth(&condition) {
+                Some(true) => expression(*then_branch),
+                Some(false) => expression(*else_branch),
+                None => Expr::Conditional {
+                    condition: Box::new(condition),
+                    then_branch: Box::new(expression(*then_branch)),
+                    else_branch: Box::new(expression(*else_branch)),
+                },
+            }
+        }
+        Expr::Get { object, name } => Expr::Get {
+            object: Box::new(expression(*object)),
+            name,
+        },
+        Expr::Grouping { expression: inner } => match expression(*inner) {
+            inner @ Expr::Literal { .. } => inner,
+            inner => Expr::Grouping {
+                expression: Box::new(inner),
+            },
+        },
+        Expr::Literal { value } => Expr::Literal { value },
+        Expr::Logical {
+            left,
+            operator,
+            right,
+        } => {
+            let left = expression(*left);
+            // `or` stops at a true operand and `and` at a false one.
+            let stops_on = operator.kind == TokenKind::Or;
+            match literal_truth(&left) {
+                Some(truth) if truth == stops_on => left,
+                Some(_) => expression(*right),
+                None => Expr::Logical {
+                    left: Box::new(left),
+                    operator,
+                    right: Box::new(expression(*right)),
+                },
+            }
+        }
+        Expr::Set {
+            object,
+            name,
+            value,
+        } => Expr::Set {
+            object: Box::new(expression(*object)),
+            name,
+            value: Box::new(expression(*value)),
+        },
+        Expr::CompoundSet {
+            object,
+            name,
+            operator,
+            value,
+        } => Expr::CompoundSet {
+            object: Box::new(expression(*object)),
+            name,
+            operator,
+            value: Box::new(expression(*value)),
+        },
+        Expr::Stringify { expression: inner } => {
+            let inner = expression(*inner);
+            let line = inner.line();
+            match literal_kind(&inner).and_then(display) {
+                Some(text) => literal(TokenKind::Str(text.as_str().into()), line),
+                None => Expr::Stringify {
+                    expression: Box::new(inner),
+                },
+            }
+        }
+        Expr::Unary { operator, right } => {
+            let right = expression(*right);
+            let folded = match (&operator.kind, literal_kind(&right)) {
+                (TokenKind::Minus, Some(TokenKind::Number(n))) => Some(TokenKind::Number(-n)),
+                (TokenKind::Bang, Some(TokenKind::True)) => Some(TokenKind::False),
+                (TokenKind::Bang, Some(TokenKind::False)) => Some(TokenKind::True),
+                _ => None,
+            };
+            match folded {
+                Some(kind) => literal(kind, operator.line),
+                None => Expr::Unary {
+                    operator,
+                    right: Box::new(right),
+                },
+            }
+        }
+        expr @ (Expr::Super { .. } | Expr::This { .. } | Expr::Variable { .. }) => expr,
+    }
+}
+
+/// The value of `left operator right` if both are literals and the operation
+/// succeeds, mirroring the interpreter's rules.
+fn fold_binary(left: &Expr, operator: &TokenKind, right: &Expr) -> Option<TokenKind> {
+    use TokenKind::*;
+    let (left, right) = (literal_kind(left)?, literal_kind(right)?);
+    Some(match (operator, left, right) {
+        (Plus, Number(n1), Number(n2)) => Number(n1 + n2),
+        (Minus, Number(n1), Number(n2)) => Number(n1 - n2),
+        (Star, Number(n1), Number(n2)) => Number(n1 * n2),
+        (Slash, Number(n1), Number(n2)) => Number(n1 / n2),
+        (Mod, Number(n1), Number(n2)) => Number(n1 % n2),
+        (Greater, Number(n1), Number(n2)) => boolean(n1 > n2),
+        (GreaterEqual, Number(n1), Number(n2)) => boolean(n1 >= n2),
+        (Less, Number(n1), Number(n2)) => boolean(n1 < n2),
+        (LessEqual, Number(n1), Number(n2)) => boolean(n1 <= n2),
+        (Plus, Str(s1), Str(s2)) => Str(format!("{}{}", s1, s2).as_str().into()),
+        (EqualEqual, left, right) => boolean(literals_equal(left, right)),
+        (BangEqual, left, right) => boolean(!literals_equal(left, right)),
+        _ => return None,
+    })
+}
+
+fn literals_equal(left: &TokenKind, right: &TokenKind) -> bool {
+    match (left, right) {
+        (TokenKind::Number(n1), TokenKind::Number(n2)) => n1 == n2,
+        (TokenKind::Str(s1), TokenKind::Str(s2)) => s1 == s2,
+        (TokenKind::True, TokenKind::True)
+        | (TokenKind::False, TokenKind::False)
+        | (TokenKind::Nil, TokenKind::Nil) => true,
+        _ => false,
+    }
+}
+
+/// Truthiness of a literal, matching [`Type::is_true`](crate::interpreter::Type::is_true).
+fn literal_truth(expr: &Expr) -> Option<bool> {
+    Some(match literal_kind(expr)? {
+        TokenKind::Number(n) => n.is_normal(),
+        TokenKind::Str(s) => !s.is_empty(),
+        TokenKind::True => true,
+        _ => false,
+    })
+}
+
+/// How a literal prints, for folding string interpolation.
+fn display(kind: &TokenKind) -> Option<String> {
+    Some(match kind {
+        TokenKind::Number(n) => crate::display::number(*n),
+        TokenKind::Str(s) => s.to_string(),
+        TokenKind::True => "true".to_string(),
+        TokenKind::False => "false".to_string(),
+        TokenKind::Nil => "nil".to_string(),
+        _ => return None,
+    })
+}
+
+fn literal_kind(expr: &Expr) -> Option<&TokenKind> {
+    match expr {
+        Expr::Literal { value } => Some(&value.kind),
+        _ => None,
+    }
+}
+
+fn literal(kind: TokenKind, line: usize) -> Expr {
+    Expr::Literal {
+        value: Token::new(kind, line),
+    }
+}
+
+fn boolean(b: bool) -> TokenKind {
+    if b {
+        TokenKind::True
+    } else {
+        TokenKind::False
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Visiter;
+    use crate::ast_printer::AstPrinter;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    fn optimized(source: &str) -> Vec<String> {
+        let tokens = Lexer::new(source).scan_tokens().unwrap();
+        let stmts = Parser::new(tokens, false).parse().unwrap();
+        optimize(stmts)
+            .iter()
+            .map(|stmt| AstPrinter.visit_stmt(stmt))
+            .collect()
+    }
+
+    #[test]
+    fn folds_constant_expressions() {
+        assert_eq!(
+            optimized(
+                "print 60 * 60 * 24; print -(2 - 3) >= 1; print \"a\" + \"b\" == \"ab\";
+                 print !(1 != 1); print \"n = ${1 + 1}\"; print x * (2 + 3);"
+            ),
+            [
+                "(print 86400)",
+                "(print true)",
+                "(print true)",
+                "(print true)",
+                "(print n = 2)",
+                "(print (* x 5))",
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_failing_operations_alone() {
+        assert_eq!(
+            optimized("print 1 + \"a\"; print -\"a\"; print !1;"),
+            ["(print (+ 1 a))", "(print (- a))", "(print (! 1))"]
+        );
+    }
+
+    #[test]
+    fn short_circuits_on_literals() {
+        assert_eq!(
+            optimized("print nil ?? x; print 1 ?? x; print false or x; print 0 and x; print true ? a : b;"),
+            ["(print x)", "(print 1)", "(print x)", "(print 0)", "(print a)"]
+        );
+    }
+
+    #[test]
+    fn removes_dead_branches_and_loops() {
+        assert_eq!(
+            optimized(
+                "if false { print 1; } if true { print 2; } if 0 print 3;
+                 while nil { print 4; }
+                 for var i = 0; false; i = i + 1 { print i; }"
+            ),
+            ["(block (print 2))", "(block (var i 0))"]
+        );
+    }
+
+    #[test]
+    fn drops_statements_after_return() {
+        assert_eq!(
+            optimized(
+                "fun f(x) { while x { return 1; print 2; } print 3; }
+                 fun g() { { return; } print 4; }"
+            ),
+            [
+                "(fun f (x) (block (while x (block (return 1))) (print 3)))",
+                "(fun g () (block (block (return))))"
+            ]
+        );
+    }
+}
@@ -1,48 +1,118 @@
 use crate::ast::{Expr, Stmt};
-use crate::lexer::Token::{self, *};
+use crate::lexer::{
+    Token,
+    TokenKind::{self, *},
+};
 
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
+use std::fmt;
 
 pub struct Parser {
     tokens: Vec<Token>,
     current: Cell<usize>,
     is_repl: bool,
+    /// Every syntax error found so far, in source order.
+    errors: RefCell<Vec<ParseError>>,
+    /// Number of blocks around the current token, so that recovering from an
+    /// error inside one stops at its closing `}`.
+    block_depth: Cell<usize>,
 }
 
+/// A syntax error, reported at the first token that does not fit the
+/// grammar, like `[line 3] Error at ';': Expect ')' after arguments.`
+#[derive(Debug)]
+struct ParseError {
+    token: Token,
+    message: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.token.kind {
+            Eof => write!(
+                f,
+                "[line {}] Error at end: {}",
+                self.token.line, self.message
+            ),
+            _ => write!(
+                f,
+                "[line {}] Error at '{}': {}",
+                self.token.line, self.token, self.message
+            ),
+        }
+    }
+}
+
+type ParseResult<T> = Result<T, ParseError>;
+
 impl Parser {
     pub fn new(tokens: Vec<Token>, is_repl: bool) -> Self {
         Self {
             tokens,
             current: Cell::new(0),
             is_repl,
+            errors: RefCell::new(Vec::new()),
+            block_depth: Cell::new(0),
         }
     }
 
+    /// Parse every statement. After an error the parser skips to the next
+    /// statement and carries on, so all errors are reported, one per line.
     pub fn parse(&self) -> Result<Vec<Stmt>, String> {
-        let mut errs = Vec::new();
         let mut statements = Vec::new();
-        while let Some(t) = self.peek() {
-            if let Eof = t {
-                break;
-            }
-            match self.declaration() {
-                Ok(statement) => statements.push(statement),
-                Err(e) => {
-                    errs.push(e);
-                    self.synchronize();
-                }
-            }
+        while !matches!(self.peek(), Some(Eof) | None) {
+            statements.extend(self.declaration_or_recover());
         }
 
-        if errs.is_empty() {
+        let errors = self.errors.borrow();
+        if errors.is_empty() {
             Ok(statements)
         } else {
-            Err(errs.join("\n"))
+            let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+            Err(errors.join("\n"))
         }
     }
 
+    /// Parse the tokens as a single expression.
+    pub fn parse_expression(&self) -> Result<Expr, String> {
+        let expr = self.expression().map_err(|e| e.to_string())?;
+        self.consume(Eof, "Expect end of expression.")
+            .map_err(|e| e.to_string())?;
+        Ok(expr)
+    }
+
+    /// Whether the last [`parse`](Self::parse) failed only because the source
+    /// ended inside an unclosed `(` or `{`, so that more input could still
+    /// complete it.
+    pub fn is_incomplete(&self) -> bool {
+        let depth = self
+            .tokens
+            .iter()
+            .fold(0i64, |depth, token| match token.kind {
+                LParen | LBrace => depth + 1,
+                RParen | RBrace => depth - 1,
+                _ => depth,
+            });
+        let reached_eof = self.errors.borrow().iter().any(|e| e.token.kind == Eof);
+        reached_eof && depth > 0
+    }
+
     // SECTION - Statements
-    fn declaration(&self) -> Result<Stmt, &'static str> {
+    /// Parse a declaration, or record its error and skip to the start of the
+    /// next statement.
+    fn declaration_or_recover(&self) -> Option<Stmt> {
+        let start = self.current.get();
+        match self.declaration() {
+            Ok(statement) => Some(statement),
+            Err(error) => {
+                self.errors.borrow_mut().push(error);
+                self.synchronize(start);
+                None
+            }
+        }
+    }
+
+    fn declaration(&self) -> ParseResult<Stmt> {
         match self.peek().expect("Current token is None") {
             Fun => self.function(),
             Var => self.var_declaration(),
@@ -50,75 +120,52 @@ impl Parser {
         }
     }
 
-    fn function(&self) -> Result<Stmt, &'static str> {
+    fn function(&self) -> ParseResult<Stmt> {
         self.advance();
-        let name = match self.peek() {
-            Some(t @ Token::Identifier(_)) => {
-                self.advance();
-                t.clone()
-            }
-            _ => return Err("Expect function name."),
-        };
-        if self.peek() == Some(&Token::LParen) {
-            self.advance();
-        } else {
-            return Err("Expect '(' after function name.");
-        }
+        let name = self.consume_identifier("Expect function name.")?;
+        self.consume(LParen, "Expect '(' after function name.")?;
         let mut params = Vec::new();
-        if self.peek() != Some(&Token::RParen) {
+        if self.peek() != Some(&RParen) {
             loop {
                 if params.len() >= 255 {
-                    return Err("Can't have more than 255 parameters.");
+                    return Err(self.error("Can't have more than 255 parameters."));
                 }
-
-                params.push(if let Some(t @ Token::Identifier(_)) = self.peek() {
-                    self.advance();
-                    t.clone()
-                } else {
-                    return Err("");
-                });
-                if self.peek() == Some(&Token::Comma) {
-                    self.advance();
-                } else {
+                params.push(self.consume_identifier("Expect parameter name.")?);
+                if !self.match_token(Comma) {
                     break;
                 }
             }
         }
-        if self.peek() == Some(&Token::RParen) {
-            self.advance();
-        } else {
-            return Err("Expect ')' after parameters.");
-        }
-        if self.peek() == Some(&Token::LBrace) {
-        } else {
-            return Err("Expct '{' before function body.");
+        self.consume(RParen, "Expect ')' after parameters.")?;
+        if self.peek() != Some(&LBrace) {
+            return Err(self.error("Expect '{' before function body."));
         }
         let body = Box::new(self.block()?);
-        Ok(Stmt::Function { name, params, body })
+        Ok(Stmt::Function {
+            name,
+            params,
+            body,
+            slot: Default::default(),
+            layout: Default::default(),
+        })
     }
 
-    fn var_declaration(&self) -> Result<Stmt, &'static str> {
+    fn var_declaration(&self) -> ParseResult<Stmt> {
         self.advance();
-        if let Some(Identifier(name)) = self.advance() {
-            let mut initializer = None;
-            if let Some(Equal) = self.peek() {
-                self.advance();
-                initializer = Some(self.expression()?);
-            }
-            if let Some(Semicolon) = self.advance() {
-                return Ok(Stmt::Var {
-                    name: Identifier(name.to_string()),
-                    initializer,
-                });
-            } else {
-                return Err("Expect ';' after variable declaration.");
-            }
-        } else {
-            return Err("Expect variable name.");
+        let name = self.consume_identifier("Expect variable name.")?;
+        let mut initializer = None;
+        if self.match_token(Equal) {
+            initializer = Some(self.expression()?);
         }
+        self.consume(Semicolon, "Expect ';' after variable declaration.")?;
+        Ok(Stmt::Var {
+            name,
+            initializer,
+            slot: Default::default(),
+        })
     }
 
-    fn statement(&self) -> Result<Stmt, &'static str> {
+    fn statement(&self) -> ParseResult<Stmt> {
         match self.peek().expect("Current token is None") {
             If => self.if_statement(),
             Print => self.print_statement(),
@@ -130,12 +177,17 @@ impl Parser {
         }
     }
 
-    fn if_statement(&self) -> Result<Stmt, &'static str> {
+    /// `if condition then else otherwise`, where `otherwise` may itself be an
+    /// `if`. An `else` belongs to the nearest `if` before it that has none.
+    ///
+    /// Conditions take no parentheses, though `if (a) ...` also works since
+    /// `(a)` is an expression too.
+    fn if_statement(&self) -> ParseResult<Stmt> {
         self.advance();
         let condition = self.expression()?;
         let then_branch = Box::new(self.statement()?);
         let mut else_branch = None;
-        if self.peek() == Some(&Token::Else) {
+        if self.match_token(Else) {
             else_branch = Some(Box::new(self.statement()?));
         }
         Ok(Stmt::If {
@@ -145,26 +197,25 @@ impl Parser {
         })
     }
 
-    fn print_statement(&self) -> Result<Stmt, &'static str> {
+    fn print_statement(&self) -> ParseResult<Stmt> {
         self.advance();
         let value = self.expression()?;
-        if self.peek() == Some(&Semicolon) {
-            self.advance();
-            Ok(Stmt::Print { expression: value })
-        } else {
-            Err("Expect ';' after value.")
-        }
+        self.consume(Semicolon, "Expect ';' after value.")?;
+        Ok(Stmt::Print { expression: value })
     }
 
-    fn for_statement(&self) -> Result<Stmt, &'static str> {
+    /// `for initializer; condition; increment body`, with the clauses
+    /// optionally wrapped in parentheses as in C.
+    fn for_statement(&self) -> ParseResult<Stmt> {
         self.advance();
+        let parenthesized = self.match_token(LParen);
         let initializer = Box::new(self.declaration()?);
         let condition = self.expression()?;
-        match self.peek() {
-            Some(Semicolon) => self.advance(),
-            _ => return Err("Expect ';' after condition."),
-        };
+        self.consume(Semicolon, "Expect ';' after condition.")?;
         let increment = self.expression()?;
+        if parenthesized {
+            self.consume(RParen, "Expect ')' after for clauses.")?;
+        }
         let body = Box::new(Stmt::Block {
             statements: vec![
                 self.statement()?,
@@ -180,116 +231,169 @@ impl Parser {
         })
     }
 
-    fn return_statement(&self) -> Result<Stmt, &'static str> {
-        let keyword = self.advance().unwrap();
+    fn return_statement(&self) -> ParseResult<Stmt> {
+        let keyword = self.advance().unwrap().clone();
         let mut value = None;
-        if self.peek() != Some(&Token::Semicolon) {
+        if self.peek() != Some(&Semicolon) {
             value = Some(self.expression()?);
         }
-        if self.peek() == Some(&Token::Semicolon) {
-            self.advance();
-        } else {
-            return Err("Expect ';' after return value.");
-        }
-        Ok(Stmt::Return {
-            keyword: keyword.clone(),
-            value,
-        })
+        self.consume(Semicolon, "Expect ';' after return value.")?;
+        Ok(Stmt::Return { keyword, value })
     }
 
-    fn while_statement(&self) -> Result<Stmt, &'static str> {
+    fn while_statement(&self) -> ParseResult<Stmt> {
         self.advance();
         let condition = self.expression()?;
         let body = Box::new(self.statement()?);
         Ok(Stmt::While { condition, body })
     }
 
-    fn block(&self) -> Result<Stmt, &'static str> {
+    /// Parse a `{ ... }` block. An error inside it is recovered from within
+    /// the block, so the rest of its statements are still checked.
+    fn block(&self) -> ParseResult<Stmt> {
         self.advance();
+        self.block_depth.set(self.block_depth.get() + 1);
         let mut statements = Vec::new();
-        while let Some(t) = self.peek() {
-            match t {
-                RBrace | Eof => {
-                    break;
-                }
-                _ => {
-                    statements.push(self.declaration()?);
-                }
-            }
-        }
-        if self.peek() == Some(&RBrace) {
-            self.advance();
-            return Ok(Stmt::Block { statements });
-        } else {
-            Err("Expect '}' after expression.")
+        while !matches!(self.peek(), Some(RBrace | Eof) | None) {
+            statements.extend(self.declaration_or_recover());
         }
+        self.block_depth.set(self.block_depth.get() - 1);
+        self.consume(RBrace, "Expect '}' after block.")?;
+        Ok(Stmt::Block { statements })
     }
 
-    fn expr_statement(&self) -> Result<Stmt, &'static str> {
+    fn expr_statement(&self) -> ParseResult<Stmt> {
         let expr = self.expression()?;
-        if self.peek() == Some(&Semicolon) {
-            self.advance();
+        if self.match_token(Semicolon) || self.is_repl {
+            // The prompt lets an expression end without a semicolon.
             Ok(Stmt::Expression { expression: expr })
-        } else if self.is_repl {
-            Ok(Stmt::Print { expression: expr })
         } else {
-            Err("Expect ';' after expression.")
+            Err(self.error("Expect ';' after expression."))
         }
     }
 
     // SECTION - Expressions
-    fn expression(&self) -> Result<Expr, &'static str> {
+    fn expression(&self) -> ParseResult<Expr> {
         self.assignment()
     }
 
-    fn assignment(&self) -> Result<Expr, &'static str> {
-        let expr = self.or()?;
-        if let Some(Token::Equal) = self.peek() {
+    /// Parse `=` and the compound assignments `+=`, `-=`, `*=`, `/=`, `%=`.
+    ///
+    /// `a op= b` desugars into `a = a op b`. A field target `obj.f op= b`
+    /// becomes an [`Expr::CompoundSet`], which evaluates `obj` only once.
+    fn assignment(&self) -> ParseResult<Expr> {
+        let expr = self.conditional()?;
+        let operator = match self.peek() {
+            Some(Equal) => None,
+            Some(PlusEqual) => Some(Plus),
+            Some(MinusEqual) => Some(Minus),
+            Some(StarEqual) => Some(Star),
+            Some(SlashEqual) => Some(Slash),
+            Some(ModEqual) => Some(Mod),
+            _ => return Ok(expr),
+        };
+        let equals = self.advance().unwrap().clone();
+        let line = equals.line;
+        let mut value = self.assignment()?;
+
+        if let (Some(operator), Expr::Get { object, name }) = (&operator, &expr) {
+            return Ok(Expr::CompoundSet {
+                object: object.clone(),
+                name: name.clone(),
+                operator: Token::new(operator.clone(), line),
+                value: Box::new(value),
+            });
+        }
+        if let Some(operator) = operator {
+            value = Expr::Binary {
+                left: Box::new(expr.clone()),
+                operator: Token::new(operator, line),
+                right: Box::new(value),
+            };
+        }
+
+        match expr {
+            Expr::Variable { name, .. } => Ok(Expr::Assign {
+                name,
+                value: Box::new(value),
+                slot: Default::default(),
+            }),
+            Expr::Get { object, name } => Ok(Expr::Set {
+                object,
+                name,
+                value: Box::new(value),
+            }),
+            _ => Err(ParseError {
+                token: equals,
+                message: "Invalid assignment target.",
+            }),
+        }
+    }
+
+    /// `condition ? then : else`, right-associative and binding looser than
+    /// `??`.
+    fn conditional(&self) -> ParseResult<Expr> {
+        let condition = self.coalesce()?;
+        if let Some(Question) = self.peek() {
+            self.advance();
+            let then_branch = self.expression()?;
+            self.consume(
+                Colon,
+                "Expect ':' after then branch of conditional expression.",
+            )?;
+            let else_branch = self.conditional()?;
+            return Ok(Expr::Conditional {
+                condition: Box::new(condition),
+                then_branch: Box::new(then_branch),
+                else_branch: Box::new(else_branch),
+            });
+        }
+        Ok(condition)
+    }
+
+    fn coalesce(&self) -> ParseResult<Expr> {
+        let mut expr = self.or()?;
+        while let Some(QuestionQuestion) = self.peek() {
             self.advance();
-            let value = self.assignment()?;
-
-            if let Expr::Variable { ref name } = expr {
-                return Ok(Expr::Assign {
-                    name: name.clone(),
-                    value: Box::new(value),
-                });
-            } else {
-                return Err("Invalid assignment target.");
+            let right = self.or()?;
+            expr = Expr::Coalesce {
+                left: Box::new(expr),
+                right: Box::new(right),
             }
         }
         Ok(expr)
     }
 
-    fn or(&self) -> Result<Expr, &'static str> {
+    fn or(&self) -> ParseResult<Expr> {
         let mut expr = self.and()?;
-        while let Some(Token::Or) = self.peek() {
-            self.advance();
+        while let Some(TokenKind::Or) = self.peek() {
+            let operator = self.advance().unwrap().clone();
             let right = self.and()?;
             expr = Expr::Logical {
                 left: Box::new(expr),
-                operator: Token::Or,
+                operator,
                 right: Box::new(right),
             }
         }
         Ok(expr)
     }
 
-    fn and(&self) -> Result<Expr, &'static str> {
+    fn and(&self) -> ParseResult<Expr> {
         let mut expr = self.equality()?;
 
-        while let Some(Token::And) = self.peek() {
-            self.advance();
+        while let Some(TokenKind::And) = self.peek() {
+            let operator = self.advance().unwrap().clone();
             let right = self.equality()?;
             expr = Expr::Logical {
                 left: Box::new(expr),
-                operator: Token::And,
+                operator,
                 right: Box::new(right),
             }
         }
         Ok(expr)
     }
 
-    fn equality(&self) -> Result<Expr, &'static str> {
+    fn equality(&self) -> ParseResult<Expr> {
         let mut expr = self.comparison()?;
 
         while let Some(operator) = match self.peek() {
@@ -307,7 +411,7 @@ impl Parser {
         Ok(expr)
     }
 
-    fn comparison(&self) -> Result<Expr, &'static str> {
+    fn comparison(&self) -> ParseResult<Expr> {
         let mut expr = self.term()?;
 
         while let Some(operator) = match self.peek() {
@@ -324,7 +428,7 @@ impl Parser {
         Ok(expr)
     }
 
-    fn term(&self) -> Result<Expr, &'static str> {
+    fn term(&self) -> ParseResult<Expr> {
         let mut expr = self.factor()?;
 
         while let Some(operator) = match self.peek() {
@@ -341,7 +445,7 @@ impl Parser {
         Ok(expr)
     }
 
-    fn factor(&self) -> Result<Expr, &'static str> {
+    fn factor(&self) -> ParseResult<Expr> {
         let mut expr = self.unary()?;
 
         while let Some(operator) = match self.peek() {
@@ -358,53 +462,56 @@ impl Parser {
         Ok(expr)
     }
 
-    fn unary(&self) -> Result<Expr, &'static str> {
+    fn unary(&self) -> ParseResult<Expr> {
         match self.peek() {
             Some(&Bang) | Some(&Minus) => {
                 let operator = self.advance().unwrap();
                 let right = self.unary()?;
-                return Ok(Expr::Unary {
+                Ok(Expr::Unary {
                     operator: operator.clone(),
                     right: Box::new(right),
-                });
+                })
             }
             _ => self.call(),
         }
     }
 
-    fn call(&self) -> Result<Expr, &'static str> {
+    fn call(&self) -> ParseResult<Expr> {
         let mut expr = self.primary()?;
         loop {
-            if let Some(LParen) = self.peek() {
-                self.advance();
-                expr = self.finish_call(expr)?;
-            } else {
-                break;
+            match self.peek() {
+                Some(LParen) => {
+                    self.advance();
+                    expr = self.finish_call(expr)?;
+                }
+                Some(Dot) => {
+                    self.advance();
+                    let name = self.consume_identifier("Expect property name after '.'.")?;
+                    expr = Expr::Get {
+                        object: Box::new(expr),
+                        name,
+                    };
+                }
+                _ => break,
             }
         }
         Ok(expr)
     }
 
-    fn finish_call(&self, callee: Expr) -> Result<Expr, &'static str> {
+    fn finish_call(&self, callee: Expr) -> ParseResult<Expr> {
         let mut arguments = Vec::new();
-        while let Some(t) = self.peek() {
-            if let Token::RParen = t {
-                break;
-            }
-            if arguments.len() >= 255 {
-                return Err("Can't have more than 255 arguments.");
-            }
-            arguments.push(self.expression()?);
-            if let Some(Token::Comma) = self.peek() {
-                self.advance();
+        if self.peek() != Some(&RParen) {
+            loop {
+                if arguments.len() >= 255 {
+                    return Err(self.error("Can't have more than 255 arguments."));
+                }
+                arguments.push(self.expression()?);
+                if !self.match_token(Comma) {
+                    break;
+                }
             }
         }
-        let r_paren = match self.peek() {
-            Some(Token::RParen) => self.advance(),
-            _ => return Err("Expect ')' after arguments."),
-        }
-        .unwrap()
-        .clone();
+        let r_paren = self.consume(RParen, "Expect ')' after arguments.")?.clone();
 
         Ok(Expr::Call {
             callee: Box::new(callee),
@@ -413,72 +520,131 @@ impl Parser {
         })
     }
 
-    fn primary(&self) -> Result<Expr, &'static str> {
+    fn primary(&self) -> ParseResult<Expr> {
         let t = self.peek();
         match t {
-            Some(False) => {
-                self.advance();
-                return Ok(Expr::Literal { value: False });
-            }
-            Some(True) => {
-                self.advance();
-                return Ok(Expr::Literal { value: True });
-            }
-            Some(Nil) => {
-                self.advance();
-                return Ok(Expr::Literal { value: Nil });
-            }
-            Some(Number(n)) => {
-                self.advance();
-                return Ok(Expr::Literal { value: Number(*n) });
-            }
-            Some(Str(ref s)) => {
-                self.advance();
-                return Ok(Expr::Literal {
-                    value: Str(s.to_string()),
-                });
-            }
-            Some(Identifier(name)) => {
+            Some(False | True | Nil | Number(_) | Str(_)) => Ok(Expr::Literal {
+                value: self.advance().unwrap().clone(),
+            }),
+            Some(Interpolation(_)) => self.interpolation(),
+            Some(Identifier(_)) => Ok(Expr::Variable {
+                name: self.advance().unwrap().clone(),
+                slot: Default::default(),
+            }),
+            Some(LParen) => {
                 self.advance();
-                Ok(Expr::Variable {
-                    name: Identifier(name.to_string()),
+                let expr = self.expression()?;
+                self.consume(RParen, "Expect ')' after expression.")?;
+                Ok(Expr::Grouping {
+                    expression: Box::new(expr),
                 })
             }
-            Some(LParen) => {
-                self.advance();
-                let expr = self.expression();
-                if let Some(RParen) = self.peek() {
-                    self.advance();
-                } else {
-                    return Err("Expect ')' after expression.");
-                }
-                return Ok(Expr::Grouping {
-                    expression: Box::new(expr?),
-                });
+            _ => Err(self.error("Expect expression.")),
+        }
+    }
+
+    /// Desugar `"a ${b} c"` into `"a " + Stringify(b) + " c"`.
+    fn interpolation(&self) -> ParseResult<Expr> {
+        let (mut expr, line) = match self.advance() {
+            Some(Token {
+                kind: Interpolation(s),
+                line,
+            }) => (
+                Expr::Literal {
+                    value: Token::new(Str(s.clone()), *line),
+                },
+                *line,
+            ),
+            _ => unreachable!(),
+        };
+        loop {
+            let value = self.expression()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator: Token::new(Plus, line),
+                right: Box::new(Expr::Stringify {
+                    expression: Box::new(value),
+                }),
+            };
+            let (segment, done) = match self.peek() {
+                Some(Interpolation(s)) => (s, false),
+                Some(Str(s)) => (s, true),
+                _ => return Err(self.error("Expect '}' after interpolated expression.")),
+            };
+            self.advance();
+            if !segment.is_empty() {
+                expr = Expr::Binary {
+                    left: Box::new(expr),
+                    operator: Token::new(Plus, line),
+                    right: Box::new(Expr::Literal {
+                        value: Token::new(Str(segment.clone()), line),
+                    }),
+                };
+            }
+            if done {
+                return Ok(expr);
             }
-            _ => Err("Expect expression."),
         }
     }
 
     // SECTION - Helpers
-    /// Discard tokens until the parser has found a statement boundary.
-    fn synchronize(&self) {
-        self.advance();
-
+    /// Discard tokens until the parser has found a statement boundary: just
+    /// after a `;` or `}`, or before a token starting a statement. Inside a
+    /// block it also stops before the `}` closing it. The failed statement
+    /// began at token `start`, which is always skipped so that parsing moves
+    /// on.
+    fn synchronize(&self, start: usize) {
         while let Some(t) = self.peek() {
-            if let &Eof = t {
-                break;
-            }
-            if let Some(&Semicolon) = self.previous() {
+            if t == &Eof || (t == &RBrace && self.block_depth.get() > 0) {
                 return;
             }
-            match t {
-                Class | Fun | Var | For | If | While | Print | Return => return,
-                _ => self.advance(),
-            };
+            if self.current.get() > start {
+                if let Some(Semicolon | RBrace) = self.previous().map(|t| &t.kind) {
+                    return;
+                }
+                if let Class | Fun | Var | For | If | While | Print | Return | LBrace = t {
+                    return;
+                }
+            }
+            self.advance();
         }
     }
 
+    /// An error at the current token.
+    fn error(&self, message: &'static str) -> ParseError {
+        let token = self
+            .peek_token()
+            .or(self.tokens.last())
+            .expect("No tokens to parse")
+            .clone();
+        ParseError { token, message }
+    }
+
+    /// Consume the current token if it is `kind`, else fail with `message`.
+    fn consume(&self, kind: TokenKind, message: &'static str) -> ParseResult<&Token> {
+        if self.peek() == Some(&kind) {
+            Ok(self.advance().unwrap())
+        } else {
+            Err(self.error(message))
+        }
+    }
+
+    fn consume_identifier(&self, message: &'static str) -> ParseResult<Token> {
+        match self.peek() {
+            Some(Identifier(_)) => Ok(self.advance().unwrap().clone()),
+            _ => Err(self.error(message)),
+        }
+    }
+
+    /// Consume the current token if it is `kind`.
+    fn match_token(&self, kind: TokenKind) -> bool {
+        let matched = self.peek() == Some(&kind);
+        if matched {
+            self.advance();
+        }
+        matched
+    }
+
     fn advance(&self) -> Option<&Token> {
         if self.current.get() >= self.tokens.len() {
             return None;
@@ -487,7 +653,11 @@ impl Parser {
         self.previous()
     }
 
-    fn peek(&self) -> Option<&Token> {
+    fn peek(&self) -> Option<&TokenKind> {
+        self.peek_token().map(|t| &t.kind)
+    }
+
+    fn peek_token(&self) -> Option<&Token> {
         self.tokens.get(self.current.get())
     }
 
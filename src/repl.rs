@@ -0,0 +1,404 @@
+//! Support for the interactive prompt.
+
+use crate::lexer::{Lexer, TokenKind, KEYWORDS};
+use crate::parser::Parser;
+use colored::Colorize;
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+use std::borrow::Cow;
+use std::env;
+use std::ops::Range;
+use std::path::PathBuf;
+
+/// Prompt for the first line of a statement.
+pub const PROMPT: &str = "> ";
+
+/// Prompt for the following lines of a statement that is not complete yet.
+pub const CONTINUATION_PROMPT: &str = "... ";
+
+/// Help text printed by `:help`.
+pub const HELP: &str = "\
+Enter Lox statements to run them. A statement left open continues on the
+next line. Commands:
+  :help          Print this help
+  :load <FILE>   Run FILE, keeping its definitions
+  :reset         Forget every definition and start over
+  :env           List the global variables and their values
+  :ast <CODE>    Print the syntax tree of CODE without running it
+  :time <CODE>   Run CODE and print how long it took
+  :quit          Leave the prompt (also Ctrl-D)
+";
+
+/// Names of the meta-commands, for completion.
+pub const META_COMMANDS: [&str; 7] = [":help", ":load", ":reset", ":env", ":ast", ":time", ":quit"];
+
+/// A line of the prompt starting with `:`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetaCommand {
+    Help,
+    Load(String),
+    Reset,
+    Env,
+    Ast(String),
+    Time(String),
+    Quit,
+}
+
+impl MetaCommand {
+    /// Parse `line` if it is a meta-command. Returns `None` for Lox code.
+    pub fn parse(line: &str) -> Option<Result<Self, String>> {
+        let line = line.trim().strip_prefix(':')?;
+        let (name, argument) = match line.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (line, ""),
+        };
+        let required = |what: &str| match argument {
+            "" => Err(format!("':{}' expects {}.", name, what)),
+            argument => Ok(argument.to_string()),
+        };
+        let no_argument = |command: MetaCommand| match argument {
+            "" => Ok(command),
+            _ => Err(format!("':{}' takes no argument.", name)),
+        };
+        Some(match name {
+            "help" | "h" => no_argument(MetaCommand::Help),
+            "load" => required("a file").map(MetaCommand::Load),
+            "reset" => no_argument(MetaCommand::Reset),
+            "env" => no_argument(MetaCommand::Env),
+            "ast" => required("some code").map(MetaCommand::Ast),
+            "time" => required("some code").map(MetaCommand::Time),
+            "quit" | "q" => no_argument(MetaCommand::Quit),
+            _ => Err(format!(
+                "Unknown command ':{}'. Type :help for a list.",
+                name
+            )),
+        })
+    }
+}
+
+/// Where the prompt keeps its history: `$XDG_STATE_HOME/lox-rs/history`,
+/// falling back to `~/.local/state/lox-rs/history`. `None` if neither
+/// variable is set.
+pub fn history_path() -> Option<PathBuf> {
+    let state = match env::var_os("XDG_STATE_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME").filter(|dir| !dir.is_empty())?)
+            .join(".local")
+            .join("state"),
+    };
+    Some(state.join("lox-rs").join("history"))
+}
+
+/// Whether `source` stops partway through a statement, inside a string, a
+/// block comment or an unclosed `(` or `{`, so the prompt should read more
+/// lines before running it.
+pub fn is_incomplete(source: &str) -> bool {
+    let mut lexer = Lexer::new(source);
+    let tokens = lexer.scan_tokens();
+    if lexer.is_incomplete() {
+        return true;
+    }
+    match tokens {
+        Ok(tokens) => {
+            let parser = Parser::new(tokens, true);
+            parser.parse().is_err() && parser.is_incomplete()
+        }
+        Err(_) => false,
+    }
+}
+
+/// Completion, syntax highlighting and bracket hints for the prompt.
+#[derive(Debug, Default)]
+pub struct LoxHelper {
+    /// The globals defined so far, offered as completions.
+    pub names: Vec<String>,
+}
+
+impl LoxHelper {
+    /// Where the word ending at byte `pos` of `line` starts, and the
+    /// keywords, globals or meta-commands it could be completed to.
+    pub fn completions(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let before = &line[..pos];
+        let start = before
+            .char_indices()
+            .rev()
+            .take_while(|&(_, c)| unicode_ident::is_xid_continue(c))
+            .last()
+            .map_or(pos, |(i, _)| i);
+        let word = &before[start..];
+        if before.trim_start() == format!(":{}", word) {
+            let commands = META_COMMANDS
+                .iter()
+                .filter(|command| command[1..].starts_with(word))
+                .map(|command| command[1..].to_string())
+                .collect();
+            return (start, commands);
+        }
+        if word.is_empty() {
+            return (start, Vec::new());
+        }
+        let mut candidates: Vec<String> = KEYWORDS
+            .iter()
+            .copied()
+            .chain(self.names.iter().map(String::as_str))
+            .filter(|candidate| candidate.starts_with(word))
+            .map(str::to_string)
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+        (start, candidates)
+    }
+}
+
+impl Completer for LoxHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let (start, candidates) = self.completions(line, pos);
+        let pairs = candidates
+            .into_iter()
+            .map(|candidate| Pair {
+                display: candidate.clone(),
+                replacement: candidate,
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for LoxHelper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<String> {
+        if pos < line.len() {
+            return None;
+        }
+        let closing = closing_brackets(line);
+        (!closing.is_empty()).then_some(closing)
+    }
+}
+
+impl Highlighter for LoxHelper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        Cow::Owned(highlight(line, pos))
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(hint.dimmed().to_string())
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize) -> bool {
+        // Moving the cursor can change which bracket pair is highlighted.
+        true
+    }
+}
+
+impl Validator for LoxHelper {}
+
+impl Helper for LoxHelper {}
+
+/// The brackets closing every `(` and `{` left open in `line`, innermost
+/// first.
+pub fn closing_brackets(line: &str) -> String {
+    let mut open = Vec::new();
+    for (kind, _) in Lexer::new(line).scan_spans() {
+        match kind {
+            TokenKind::LParen => open.push(')'),
+            TokenKind::LBrace => open.push('}'),
+            TokenKind::RParen | TokenKind::RBrace => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+    open.iter().rev().collect()
+}
+
+/// `line` with its tokens colored by kind, and the bracket next to byte
+/// `cursor` and the one matching it in bold.
+pub fn highlight(line: &str, cursor: usize) -> String {
+    // Spans count characters; slicing `line` needs byte offsets.
+    let offsets: Vec<usize> = line
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain([line.len()])
+        .collect();
+    let spans = Lexer::new(line).scan_spans();
+    let brackets = matching_brackets(&spans, line[..cursor].chars().count());
+
+    let mut out = String::new();
+    let mut last = 0;
+    for (i, (kind, range)) in spans.iter().enumerate() {
+        let (start, end) = (offsets[range.start], offsets[range.end]);
+        out.push_str(&highlight_gap(&line[last..start]));
+        let text = &line[start..end];
+        let text = match kind {
+            _ if brackets.contains(&i) => text.bold().blue(),
+            TokenKind::Number(_) => text.yellow(),
+            TokenKind::Str(_) | TokenKind::Interpolation(_) => text.green(),
+            TokenKind::True | TokenKind::False | TokenKind::Nil => text.cyan(),
+            _ if KEYWORDS.contains(&text) => text.magenta().bold(),
+            _ => text.normal(),
+        };
+        out.push_str(&text.to_string());
+        last = end;
+    }
+    out.push_str(&highlight_gap(&line[last..]));
+    out
+}
+
+/// Text between tokens: whitespace, then a comment or something that did not
+/// lex, such as an unterminated string.
+fn highlight_gap(gap: &str) -> String {
+    let rest = gap.trim_start();
+    let (space, rest) = gap.split_at(gap.len() - rest.len());
+    let rest = if rest.starts_with('/') {
+        rest.dimmed()
+    } else if rest.starts_with('"') {
+        rest.green()
+    } else {
+        rest.normal()
+    };
+    format!("{}{}", space, rest)
+}
+
+/// Indices in `spans` of the bracket starting or ending at character
+/// `cursor` and of the bracket matching it.
+fn matching_brackets(spans: &[(TokenKind, Range<usize>)], cursor: usize) -> Vec<usize> {
+    let mut open = Vec::new();
+    for (i, (kind, _)) in spans.iter().enumerate() {
+        let close = match kind {
+            TokenKind::LParen | TokenKind::LBrace => {
+                open.push(i);
+                continue;
+            }
+            TokenKind::RParen | TokenKind::RBrace => i,
+            _ => continue,
+        };
+        let Some(start) = open.pop() else { continue };
+        let touches = |i: usize| spans[i].1.start == cursor || spans[i].1.end == cursor;
+        if touches(start) || touches(close) {
+            return vec![start, close];
+        }
+    }
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unfinished_statements_are_incomplete() {
+        assert!(is_incomplete("fun f() {\n"));
+        assert!(is_incomplete(
+            "fun f() {\n  if true {\n    return 1;\n  }\n"
+        ));
+        assert!(is_incomplete("print max(1,\n"));
+        assert!(is_incomplete("print (1 +\n"));
+        assert!(is_incomplete("print \"first line\n"));
+        assert!(is_incomplete("/* a comment\n"));
+    }
+
+    #[test]
+    fn meta_commands() {
+        assert_eq!(MetaCommand::parse("print 1;"), None);
+        assert_eq!(MetaCommand::parse(" :help "), Some(Ok(MetaCommand::Help)));
+        assert_eq!(MetaCommand::parse(":q"), Some(Ok(MetaCommand::Quit)));
+        assert_eq!(
+            MetaCommand::parse(":load  lib/util.lox "),
+            Some(Ok(MetaCommand::Load("lib/util.lox".into())))
+        );
+        assert_eq!(
+            MetaCommand::parse(":time fib(20)"),
+            Some(Ok(MetaCommand::Time("fib(20)".into())))
+        );
+        assert_eq!(
+            MetaCommand::parse(":ast"),
+            Some(Err("':ast' expects some code.".into()))
+        );
+        assert_eq!(
+            MetaCommand::parse(":env x"),
+            Some(Err("':env' takes no argument.".into()))
+        );
+        assert_eq!(
+            MetaCommand::parse(":exit"),
+            Some(Err("Unknown command ':exit'. Type :help for a list.".into()))
+        );
+    }
+
+    #[test]
+    fn completes_keywords_globals_and_commands() {
+        let helper = LoxHelper {
+            names: vec!["fibonacci".into(), "print_all".into(), "clock".into()],
+        };
+        assert_eq!(
+            helper.completions("var x = fi", 10),
+            (8, vec!["fibonacci".to_string()])
+        );
+        assert_eq!(
+            helper.completions("pr", 2),
+            (0, vec!["print".to_string(), "print_all".to_string()])
+        );
+        assert_eq!(
+            helper.completions("f", 1),
+            (
+                0,
+                vec![
+                    "false".to_string(),
+                    "fibonacci".to_string(),
+                    "for".to_string(),
+                    "fun".to_string()
+                ]
+            )
+        );
+        assert_eq!(helper.completions("cl + 1", 2).1, ["class", "clock"]);
+        assert_eq!(helper.completions("1 + ", 4).1, Vec::<String>::new());
+        assert_eq!(helper.completions(":r", 2), (1, vec!["reset".to_string()]));
+    }
+
+    #[test]
+    fn hints_closing_brackets() {
+        assert_eq!(closing_brackets("fun f(a) { if (a"), ")}");
+        assert_eq!(closing_brackets("print \"(\" + f(1)"), "");
+        assert_eq!(closing_brackets("}"), "");
+    }
+
+    #[test]
+    fn highlights_tokens_and_matching_bracket() {
+        colored::control::set_override(true);
+        let line = "print f(1, \"s\") // note";
+        let plain = highlight(line, 0);
+        assert!(plain.contains(&"print".magenta().bold().to_string()));
+        assert!(plain.contains(&"1".yellow().to_string()));
+        assert!(plain.contains(&"\"s\"".green().to_string()));
+        assert!(plain.contains(&"// note".dimmed().to_string()));
+        assert!(!plain.contains(&"(".bold().blue().to_string()));
+        let at_paren = highlight(line, 7);
+        assert!(at_paren.contains(&"(".bold().blue().to_string()));
+        assert!(at_paren.contains(&")".bold().blue().to_string()));
+        assert_eq!(
+            highlight("x = \"unterminated", 0),
+            format!("x = {}", "\"unterminated".green())
+        );
+    }
+
+    #[test]
+    fn complete_or_broken_statements_are_not() {
+        assert!(!is_incomplete("fun f() {\n  return 1;\n}\n"));
+        assert!(!is_incomplete("1 + 2\n"));
+        assert!(!is_incomplete("print 1 +\n"));
+        assert!(!is_incomplete("var x = 1\n"));
+        assert!(!is_incomplete("print (1;\n"));
+        assert!(!is_incomplete("}\n"));
+        assert!(!is_incomplete("\n"));
+    }
+}
@@ -0,0 +1,63 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// Name under which code outside of any function is traced.
+pub const SCRIPT: &str = "<script>";
+
+/// Execution log shared by the tree-walker and the bytecode VM.
+///
+/// Every executed statement (or instruction) becomes one line holding its
+/// source line, call depth, enclosing function and what was executed,
+/// followed by an indented line with the bindings in scope (or the value
+/// stack).
+pub struct Tracer {
+    out: Box<dyn Write>,
+    function: Option<String>,
+}
+
+impl Tracer {
+    pub fn new(out: Box<dyn Write>) -> Self {
+        Self {
+            out,
+            function: None,
+        }
+    }
+
+    pub fn stderr() -> Self {
+        Self::new(Box::new(io::stderr()))
+    }
+
+    pub fn file(path: &str) -> io::Result<Self> {
+        Ok(Self::new(Box::new(BufWriter::new(File::create(path)?))))
+    }
+
+    /// Only log what executes directly inside functions called `name`. Use
+    /// [`SCRIPT`] for top-level code.
+    pub fn only_in(mut self, name: &str) -> Self {
+        self.function = Some(name.to_string());
+        self
+    }
+
+    /// Whether events inside `function` should be logged.
+    pub fn traces(&self, function: &str) -> bool {
+        self.function.as_deref().is_none_or(|name| name == function)
+    }
+
+    pub fn log(&mut self, line: usize, depth: usize, function: &str, event: &str, state: &str) {
+        // A broken trace sink must not abort the program being traced.
+        let _ = writeln!(
+            self.out,
+            "[line {}] depth {} in {}: {}",
+            line, depth, function, event
+        );
+        if !state.is_empty() {
+            let _ = writeln!(self.out, "    {}", state);
+        }
+    }
+}
+
+impl Drop for Tracer {
+    fn drop(&mut self) {
+        let _ = self.out.flush();
+    }
+}
@@ -0,0 +1,162 @@
+use super::value::Value;
+
+/// Instructions understood by the [`Vm`](super::Vm). Operands follow the
+/// opcode byte in the chunk; multi-byte operands are big-endian `u16`s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OpCode {
+    /// `u16` constant index.
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    /// Push a copy of the value on top of the stack.
+    Dup,
+    /// `u8` stack slot relative to the frame.
+    GetLocal,
+    /// `u8` stack slot relative to the frame.
+    SetLocal,
+    /// `u16` constant index of the name.
+    GetGlobal,
+    /// `u16` constant index of the name.
+    DefineGlobal,
+    /// `u16` constant index of the name.
+    SetGlobal,
+    /// `u8` upvalue index.
+    GetUpvalue,
+    /// `u8` upvalue index.
+    SetUpvalue,
+    /// `u16` constant index of the property name.
+    GetProperty,
+    /// `u16` constant index of the property name.
+    SetProperty,
+    Equal,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Not,
+    Negate,
+    Stringify,
+    Print,
+    /// `u16` forward offset.
+    Jump,
+    /// `u16` forward offset; leaves the condition on the stack.
+    JumpIfFalse,
+    /// `u16` forward offset; leaves the value on the stack.
+    JumpIfNotNil,
+    /// `u16` backward offset.
+    Loop,
+    /// `u8` argument count.
+    Call,
+    /// `u16` constant index of the function, then one `(is_local: u8,
+    /// index: u8)` pair per upvalue.
+    Closure,
+    CloseUpvalue,
+    Return,
+}
+
+impl OpCode {
+    const ALL: [OpCode; 37] = [
+        OpCode::Constant,
+        OpCode::Nil,
+        OpCode::True,
+        OpCode::False,
+        OpCode::Pop,
+        OpCode::Dup,
+        OpCode::GetLocal,
+        OpCode::SetLocal,
+        OpCode::GetGlobal,
+        OpCode::DefineGlobal,
+        OpCode::SetGlobal,
+        OpCode::GetUpvalue,
+        OpCode::SetUpvalue,
+        OpCode::GetProperty,
+        OpCode::SetProperty,
+        OpCode::Equal,
+        OpCode::Greater,
+        OpCode::GreaterEqual,
+        OpCode::Less,
+        OpCode::LessEqual,
+        OpCode::Add,
+        OpCode::Subtract,
+        OpCode::Multiply,
+        OpCode::Divide,
+        OpCode::Modulo,
+        OpCode::Not,
+        OpCode::Negate,
+        OpCode::Stringify,
+        OpCode::Print,
+        OpCode::Jump,
+        OpCode::JumpIfFalse,
+        OpCode::JumpIfNotNil,
+        OpCode::Loop,
+        OpCode::Call,
+        OpCode::Closure,
+        OpCode::CloseUpvalue,
+        OpCode::Return,
+    ];
+
+    pub fn from_byte(byte: u8) -> Option<OpCode> {
+        Self::ALL.get(byte as usize).copied()
+    }
+}
+
+/// A compiled sequence of bytecode with its constant pool and, for every
+/// byte of code, the source line it came from.
+#[derive(Debug, Clone, Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub lines: Vec<usize>,
+    pub constants: Vec<Value>,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write(&mut self, byte: u8, line: usize) {
+        self.code.push(byte);
+        self.lines.push(line);
+    }
+
+    pub fn write_op(&mut self, op: OpCode, line: usize) {
+        self.write(op as u8, line);
+    }
+
+    pub fn write_u16(&mut self, value: u16, line: usize) {
+        let [high, low] = value.to_be_bytes();
+        self.write(high, line);
+        self.write(low, line);
+    }
+
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_be_bytes([self.code[offset], self.code[offset + 1]])
+    }
+
+    /// Add `value` to the constant pool and return its index.
+    pub fn add_constant(&mut self, value: Value) -> usize {
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opcodes_round_trip_through_bytes() {
+        for op in OpCode::ALL {
+            assert_eq!(OpCode::from_byte(op as u8), Some(op));
+        }
+        assert_eq!(OpCode::from_byte(OpCode::ALL.len() as u8), None);
+    }
+}
@@ -0,0 +1,573 @@
+use super::chunk::{Chunk, OpCode};
+use super::value::{Function, Value};
+use crate::ast::{Expr, Stmt};
+use crate::interner::Symbol;
+use crate::lexer::{Token, TokenKind};
+use std::rc::Rc;
+
+/// Single-pass compiler from the syntax tree to bytecode.
+///
+/// Locals live in stack slots resolved at compile time, variables captured
+/// by inner functions become upvalues and everything declared at the top
+/// level is a global looked up by name.
+pub struct Compiler {
+    states: Vec<FunctionState>,
+    line: usize,
+}
+
+#[derive(Default)]
+struct FunctionState {
+    function: Function,
+    locals: Vec<Local>,
+    upvalues: Vec<UpvalueRef>,
+    scope_depth: usize,
+}
+
+struct Local {
+    name: Symbol,
+    depth: usize,
+    captured: bool,
+}
+
+#[derive(PartialEq)]
+struct UpvalueRef {
+    index: u8,
+    is_local: bool,
+}
+
+type CompileResult = Result<(), String>;
+
+/// Deepest nesting of functions, counting the top-level script. Loading a
+/// compiled file enforces it too.
+pub(super) const NESTING_MAX: usize = 256;
+
+impl Compiler {
+    /// Compile a whole program into the function for its top-level script.
+    pub fn compile(statements: &[Stmt]) -> Result<Function, String> {
+        let mut compiler = Self {
+            states: vec![FunctionState::new(None)],
+            line: 1,
+        };
+        for stmt in statements {
+            compiler.statement(stmt)?;
+        }
+        compiler.emit_op(OpCode::Nil);
+        compiler.emit_op(OpCode::Return);
+        Ok(compiler.states.pop().unwrap().function)
+    }
+
+    /// Compile a top-level expression into a script returning its value.
+    pub fn compile_expression(expression: &Expr) -> Result<Function, String> {
+        let mut compiler = Self {
+            states: vec![FunctionState::new(None)],
+            line: 1,
+        };
+        compiler.expression(expression)?;
+        compiler.emit_op(OpCode::Return);
+        Ok(compiler.states.pop().unwrap().function)
+    }
+
+    // SECTION - Statements
+    fn statement(&mut self, stmt: &Stmt) -> CompileResult {
+        match stmt {
+            Stmt::Expression { expression } => {
+                self.expression(expression)?;
+                self.emit_op(OpCode::Pop);
+            }
+            Stmt::Print { expression } => {
+                self.expression(expression)?;
+                self.emit_op(OpCode::Print);
+            }
+            Stmt::Var {
+                name, initializer, ..
+            } => {
+                self.line = name.line;
+                match initializer {
+                    Some(initializer) => self.expression(initializer)?,
+                    None => self.emit_op(OpCode::Nil),
+                }
+                self.define_variable(name)?;
+            }
+            Stmt::Function {
+                name, params, body, ..
+            } => {
+                self.line = name.line;
+                // Declare the name first so the body can refer to itself.
+                if self.state().scope_depth > 0 {
+                    self.add_local(name)?;
+                }
+                self.function(name, params, body)?;
+                if self.state().scope_depth == 0 {
+                    self.define_variable(name)?;
+                }
+            }
+            Stmt::Return { keyword, value } => {
+                self.line = keyword.line;
+                if self.states.len() == 1 {
+                    return Err(self.error("Only functions can return values."));
+                }
+                match value {
+                    Some(value) => self.expression(value)?,
+                    None => self.emit_op(OpCode::Nil),
+                }
+                self.emit_op(OpCode::Return);
+            }
+            Stmt::Block { statements } => {
+                self.begin_scope();
+                for stmt in statements {
+                    self.statement(stmt)?;
+                }
+                self.end_scope();
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(condition)?;
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+                self.statement(then_branch)?;
+                let else_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(then_jump)?;
+                self.emit_op(OpCode::Pop);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch)?;
+                }
+                self.patch_jump(else_jump)?;
+            }
+            Stmt::While { condition, body } => self.while_loop(condition, body)?,
+            Stmt::For {
+                initializer,
+                condition,
+                body,
+            } => {
+                self.begin_scope();
+                self.statement(initializer)?;
+                self.while_loop(condition, body)?;
+                self.end_scope();
+            }
+            Stmt::Class { name, .. } => {
+                self.line = name.line;
+                return Err(self.error("Classes are not supported by the bytecode backend."));
+            }
+        }
+        Ok(())
+    }
+
+    fn while_loop(&mut self, condition: &Expr, body: &Stmt) -> CompileResult {
+        let loop_start = self.chunk().code.len();
+        self.expression(condition)?;
+        let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_op(OpCode::Pop);
+        self.statement(body)?;
+        self.emit_loop(loop_start)?;
+        self.patch_jump(exit_jump)?;
+        self.emit_op(OpCode::Pop);
+        Ok(())
+    }
+
+    fn function(&mut self, name: &Token, params: &[Token], body: &Stmt) -> CompileResult {
+        if self.states.len() == NESTING_MAX {
+            return Err(self.error("Too many nested functions."));
+        }
+        let mut state = FunctionState::new(Some(name.to_string()));
+        state.function.arity = params.len();
+        self.states.push(state);
+        self.begin_scope();
+        for param in params {
+            self.add_local(param)?;
+        }
+        match body {
+            Stmt::Block { statements } => {
+                for stmt in statements {
+                    self.statement(stmt)?;
+                }
+            }
+            _ => self.statement(body)?,
+        }
+        self.emit_op(OpCode::Nil);
+        self.emit_op(OpCode::Return);
+
+        let state = self.states.pop().unwrap();
+        self.line = name.line;
+        let mut function = state.function;
+        function.upvalue_count = state.upvalues.len();
+        let constant = self.make_constant(Value::function(Rc::new(function)))?;
+        self.emit_op(OpCode::Closure);
+        self.emit_u16(constant);
+        for upvalue in state.upvalues {
+            self.emit_byte(upvalue.is_local as u8);
+            self.emit_byte(upvalue.index);
+        }
+        Ok(())
+    }
+
+    // SECTION - Expressions
+    fn expression(&mut self, expr: &Expr) -> CompileResult {
+        match expr {
+            Expr::Literal { value } => {
+                self.line = value.line;
+                match &value.kind {
+                    TokenKind::Number(n) => self.emit_constant(Value::number(*n))?,
+                    TokenKind::Str(s) => self.emit_constant(Value::string(Rc::clone(s.as_rc())))?,
+                    TokenKind::True => self.emit_op(OpCode::True),
+                    TokenKind::False => self.emit_op(OpCode::False),
+                    TokenKind::Nil => self.emit_op(OpCode::Nil),
+                    _ => return Err(self.error("Unexpected literal.")),
+                }
+            }
+            Expr::Grouping { expression } => self.expression(expression)?,
+            Expr::Stringify { expression } => {
+                self.expression(expression)?;
+                self.emit_op(OpCode::Stringify);
+            }
+            Expr::Unary { operator, right } => {
+                self.expression(right)?;
+                self.line = operator.line;
+                match operator.kind {
+                    TokenKind::Minus => self.emit_op(OpCode::Negate),
+                    TokenKind::Bang => self.emit_op(OpCode::Not),
+                    _ => return Err(self.error("Unexpected unary operator.")),
+                }
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                self.expression(left)?;
+                self.expression(right)?;
+                self.binary_operator(operator)?;
+            }
+            Expr::Logical {
+                left,
+                operator,
+                right,
+            } => {
+                self.expression(left)?;
+                self.line = operator.line;
+                match operator.kind {
+                    TokenKind::And => {
+                        let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+                        self.emit_op(OpCode::Pop);
+                        self.expression(right)?;
+                        self.patch_jump(end_jump)?;
+                    }
+                    TokenKind::Or => {
+                        let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                        let end_jump = self.emit_jump(OpCode::Jump);
+                        self.patch_jump(else_jump)?;
+                        self.emit_op(OpCode::Pop);
+                        self.expression(right)?;
+                        self.patch_jump(end_jump)?;
+                    }
+                    _ => return Err(self.error("Unexpected logical operator.")),
+                }
+            }
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(condition)?;
+                let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+                self.expression(then_branch)?;
+                let end_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(else_jump)?;
+                self.emit_op(OpCode::Pop);
+                self.expression(else_branch)?;
+                self.patch_jump(end_jump)?;
+            }
+            Expr::Coalesce { left, right } => {
+                self.expression(left)?;
+                let end_jump = self.emit_jump(OpCode::JumpIfNotNil);
+                self.emit_op(OpCode::Pop);
+                self.expression(right)?;
+                self.patch_jump(end_jump)?;
+            }
+            Expr::Variable { name, .. } => {
+                self.line = name.line;
+                self.named_variable(name, false)?;
+            }
+            Expr::Assign { name, value, .. } => {
+                self.expression(value)?;
+                self.line = name.line;
+                self.named_variable(name, true)?;
+            }
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+            } => {
+                self.expression(callee)?;
+                for argument in arguments {
+                    self.expression(argument)?;
+                }
+                self.line = paren.line;
+                self.emit_op(OpCode::Call);
+                self.emit_byte(arguments.len() as u8);
+            }
+            Expr::Get { object, name } => {
+                self.expression(object)?;
+                self.line = name.line;
+                let constant = self.identifier_constant(name)?;
+                self.emit_op(OpCode::GetProperty);
+                self.emit_u16(constant);
+            }
+            Expr::Set {
+                object,
+                name,
+                value,
+            } => {
+                self.expression(object)?;
+                self.expression(value)?;
+                self.line = name.line;
+                let constant = self.identifier_constant(name)?;
+                self.emit_op(OpCode::SetProperty);
+                self.emit_u16(constant);
+            }
+            Expr::CompoundSet {
+                object,
+                name,
+                operator,
+                value,
+            } => {
+                // Keep the object under the field's value for the set.
+                self.expression(object)?;
+                self.emit_op(OpCode::Dup);
+                self.line = name.line;
+                let constant = self.identifier_constant(name)?;
+                self.emit_op(OpCode::GetProperty);
+                self.emit_u16(constant);
+                self.expression(value)?;
+                self.binary_operator(operator)?;
+                self.line = name.line;
+                self.emit_op(OpCode::SetProperty);
+                self.emit_u16(constant);
+            }
+            Expr::Super { keyword, .. } | Expr::This { keyword } => {
+                self.line = keyword.line;
+                return Err(self.error(&format!("Can't use '{}' outside of a class.", keyword)));
+            }
+        }
+        Ok(())
+    }
+
+    // SECTION - Variables
+    fn named_variable(&mut self, name: &Token, assign: bool) -> CompileResult {
+        let top = self.states.len() - 1;
+        let (get, set, operand) = if let Some(slot) = self.resolve_local(top, name) {
+            (OpCode::GetLocal, OpCode::SetLocal, slot as u16)
+        } else if let Some(index) = self.resolve_upvalue(top, name)? {
+            (OpCode::GetUpvalue, OpCode::SetUpvalue, index as u16)
+        } else {
+            let constant = self.identifier_constant(name)?;
+            (OpCode::GetGlobal, OpCode::SetGlobal, constant)
+        };
+        self.emit_op(if assign { set } else { get });
+        match get {
+            OpCode::GetGlobal => self.emit_u16(operand),
+            _ => self.emit_byte(operand as u8),
+        }
+        Ok(())
+    }
+
+    /// Bind the value on top of the stack to `name` in the current scope.
+    fn define_variable(&mut self, name: &Token) -> CompileResult {
+        if self.state().scope_depth > 0 {
+            // The value is already sitting in the new local's slot.
+            return self.add_local(name);
+        }
+        let constant = self.identifier_constant(name)?;
+        self.emit_op(OpCode::DefineGlobal);
+        self.emit_u16(constant);
+        Ok(())
+    }
+
+    fn add_local(&mut self, name: &Token) -> CompileResult {
+        if self.state().locals.len() > u8::MAX as usize {
+            return Err(self.error("Too many local variables in function."));
+        }
+        let depth = self.state().scope_depth;
+        self.state_mut().locals.push(Local {
+            name: symbol(name),
+            depth,
+            captured: false,
+        });
+        Ok(())
+    }
+
+    fn resolve_local(&self, state: usize, name: &Token) -> Option<usize> {
+        let name = symbol(name);
+        self.states[state]
+            .locals
+            .iter()
+            .rposition(|local| local.name == name)
+    }
+
+    fn resolve_upvalue(&mut self, state: usize, name: &Token) -> Result<Option<u8>, String> {
+        if state == 0 {
+            return Ok(None);
+        }
+        if let Some(slot) = self.resolve_local(state - 1, name) {
+            self.states[state - 1].locals[slot].captured = true;
+            return self.add_upvalue(state, slot as u8, true).map(Some);
+        }
+        match self.resolve_upvalue(state - 1, name)? {
+            Some(index) => self.add_upvalue(state, index, false).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn add_upvalue(&mut self, state: usize, index: u8, is_local: bool) -> Result<u8, String> {
+        let upvalue = UpvalueRef { index, is_local };
+        let upvalues = &mut self.states[state].upvalues;
+        if let Some(existing) = upvalues.iter().position(|u| *u == upvalue) {
+            return Ok(existing as u8);
+        }
+        if upvalues.len() > u8::MAX as usize {
+            return Err(self.error("Too many closure variables in function."));
+        }
+        upvalues.push(upvalue);
+        Ok((upvalues.len() - 1) as u8)
+    }
+
+    /// Emit the instruction for the arithmetic or comparison `operator`,
+    /// whose operands are on the stack.
+    fn binary_operator(&mut self, operator: &Token) -> CompileResult {
+        self.line = operator.line;
+        match operator.kind {
+            TokenKind::Plus => self.emit_op(OpCode::Add),
+            TokenKind::Minus => self.emit_op(OpCode::Subtract),
+            TokenKind::Star => self.emit_op(OpCode::Multiply),
+            TokenKind::Slash => self.emit_op(OpCode::Divide),
+            TokenKind::Mod => self.emit_op(OpCode::Modulo),
+            TokenKind::Greater => self.emit_op(OpCode::Greater),
+            TokenKind::GreaterEqual => self.emit_op(OpCode::GreaterEqual),
+            TokenKind::Less => self.emit_op(OpCode::Less),
+            TokenKind::LessEqual => self.emit_op(OpCode::LessEqual),
+            TokenKind::EqualEqual => self.emit_op(OpCode::Equal),
+            TokenKind::BangEqual => {
+                self.emit_op(OpCode::Equal);
+                self.emit_op(OpCode::Not);
+            }
+            _ => return Err(self.error("Unexpected binary operator.")),
+        }
+        Ok(())
+    }
+
+    fn identifier_constant(&mut self, name: &Token) -> Result<u16, String> {
+        self.make_constant(Value::string(Rc::clone(symbol(name).as_rc())))
+    }
+
+    fn begin_scope(&mut self) {
+        self.state_mut().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        let state = self.state_mut();
+        state.scope_depth -= 1;
+        let depth = state.scope_depth;
+        while let Some(local) = self.state().locals.last() {
+            if local.depth <= depth {
+                break;
+            }
+            if local.captured {
+                self.emit_op(OpCode::CloseUpvalue);
+            } else {
+                self.emit_op(OpCode::Pop);
+            }
+            self.state_mut().locals.pop();
+        }
+    }
+
+    // SECTION - Helpers
+    fn state(&self) -> &FunctionState {
+        self.states.last().unwrap()
+    }
+
+    fn state_mut(&mut self) -> &mut FunctionState {
+        self.states.last_mut().unwrap()
+    }
+
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.state_mut().function.chunk
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("[line {}] Error: {}", self.line, message)
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        let line = self.line;
+        self.chunk().write(byte, line);
+    }
+
+    fn emit_op(&mut self, op: OpCode) {
+        self.emit_byte(op as u8);
+    }
+
+    fn emit_u16(&mut self, value: u16) {
+        let line = self.line;
+        self.chunk().write_u16(value, line);
+    }
+
+    fn make_constant(&mut self, value: Value) -> Result<u16, String> {
+        let constant = self.chunk().add_constant(value);
+        u16::try_from(constant).map_err(|_| self.error("Too many constants in one chunk."))
+    }
+
+    fn emit_constant(&mut self, value: Value) -> CompileResult {
+        let constant = self.make_constant(value)?;
+        self.emit_op(OpCode::Constant);
+        self.emit_u16(constant);
+        Ok(())
+    }
+
+    /// Emit a jump with a placeholder offset and return the offset's position.
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit_op(op);
+        self.emit_u16(u16::MAX);
+        self.chunk().code.len() - 2
+    }
+
+    fn patch_jump(&mut self, offset: usize) -> CompileResult {
+        let jump = self.chunk().code.len() - offset - 2;
+        let jump = u16::try_from(jump).map_err(|_| self.error("Too much code to jump over."))?;
+        let [high, low] = jump.to_be_bytes();
+        self.chunk().code[offset] = high;
+        self.chunk().code[offset + 1] = low;
+        Ok(())
+    }
+
+    fn emit_loop(&mut self, loop_start: usize) -> CompileResult {
+        self.emit_op(OpCode::Loop);
+        let offset = self.chunk().code.len() - loop_start + 2;
+        let offset = u16::try_from(offset).map_err(|_| self.error("Loop body too large."))?;
+        self.emit_u16(offset);
+        Ok(())
+    }
+}
+
+impl FunctionState {
+    fn new(name: Option<String>) -> Self {
+        let mut state = Self::default();
+        state.function.name = name;
+        // Slot zero holds the function being called.
+        state.locals.push(Local {
+            name: Symbol::intern(""),
+            depth: 0,
+            captured: false,
+        });
+        state
+    }
+}
+
+fn symbol(name: &Token) -> Symbol {
+    match &name.kind {
+        TokenKind::Identifier(symbol) => symbol.clone(),
+        _ => unreachable!("Variable names are identifiers"),
+    }
+}
@@ -0,0 +1,493 @@
+use super::chunk::OpCode;
+use super::compiler::Compiler;
+use super::debug;
+use super::natives;
+use super::value::{Closure, Function, Native, Unpacked, Upvalue, Value};
+use crate::ast::Stmt;
+use crate::trace::{self, Tracer};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Deepest call stack either backend allows, counting the top-level script.
+pub const FRAMES_MAX: usize = 1024;
+
+/// Stack-based virtual machine executing compiled [`Chunk`](super::Chunk)s.
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    globals: HashMap<Rc<str>, Value>,
+    /// Upvalues still pointing into the stack, sorted by slot.
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    tracer: Option<Tracer>,
+}
+
+struct CallFrame {
+    closure: Rc<Closure>,
+    ip: usize,
+    /// Index of the frame's slot zero in the value stack.
+    slots: usize,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        let mut vm = Self {
+            stack: Vec::with_capacity(256),
+            frames: Vec::with_capacity(64),
+            globals: HashMap::new(),
+            open_upvalues: Vec::new(),
+            tracer: None,
+        };
+        vm.define_native(Native {
+            name: "clock",
+            arity: 0,
+            function: Box::new(natives::clock),
+        });
+        vm.set_script_args(&[]);
+        vm
+    }
+
+    /// Expose `args` to the program through the `argCount()` and
+    /// `arg(index)` natives.
+    pub fn set_script_args(&mut self, args: &[String]) {
+        let args: Rc<[Rc<str>]> = args.iter().map(|arg| arg.as_str().into()).collect();
+        let count = Value::number(args.len() as f64);
+        self.define_native(Native {
+            name: "argCount",
+            arity: 0,
+            function: Box::new(move |_| Ok(count.clone())),
+        });
+        self.define_native(Native {
+            name: "arg",
+            arity: 1,
+            function: Box::new(move |values| Ok(natives::arg(&args, &values[0]))),
+        });
+    }
+
+    /// Log every instruction executed from now on to `tracer`.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    /// Compile and run a program. Globals persist between calls.
+    pub fn interpret(&mut self, statements: &[Stmt]) -> Result<(), String> {
+        let function = Compiler::compile(statements)?;
+        self.run_function(function)
+    }
+
+    /// Run the top-level function produced by [`Compiler::compile`].
+    pub fn run_function(&mut self, function: Function) -> Result<(), String> {
+        self.execute(function).map(|_| ())
+    }
+
+    /// Run the function produced by [`Compiler::compile_expression`] and
+    /// return the expression's value.
+    pub fn evaluate_function(&mut self, function: Function) -> Result<Value, String> {
+        self.execute(function)
+    }
+
+    /// Run a top-level function and return the value it returns.
+    fn execute(&mut self, function: Function) -> Result<Value, String> {
+        let closure = Rc::new(Closure {
+            function: Rc::new(function),
+            upvalues: Vec::new(),
+        });
+        self.stack.push(Value::closure(Rc::clone(&closure)));
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            slots: 0,
+        });
+        let result = self.run();
+        if result.is_err() {
+            self.stack.clear();
+            self.frames.clear();
+            self.open_upvalues.clear();
+        }
+        result
+    }
+
+    /// Every defined global with its value, sorted by name.
+    pub fn globals(&self) -> Vec<(Rc<str>, Value)> {
+        let mut globals: Vec<(Rc<str>, Value)> = self
+            .globals
+            .iter()
+            .map(|(name, value)| (Rc::clone(name), value.clone()))
+            .collect();
+        globals.sort_by(|a, b| a.0.cmp(&b.0));
+        globals
+    }
+
+    fn define_native(&mut self, native: Native) {
+        self.globals
+            .insert(native.name.into(), Value::native(Rc::new(native)));
+    }
+
+    fn run(&mut self) -> Result<Value, String> {
+        let frame = self.frames.last().unwrap();
+        let mut closure = Rc::clone(&frame.closure);
+        let mut ip = frame.ip;
+        let mut slots = frame.slots;
+        self.dispatch(&mut closure, &mut ip, &mut slots)
+            .map_err(|message| {
+                let line = closure.function.chunk.lines[ip - 1];
+                format!("{}\n[line {}]", message, line)
+            })
+    }
+
+    /// Execute instructions from `ip` in `closure`, whose frame starts at
+    /// `slots`, until the script returns. On error, `closure` and `ip` are
+    /// left just past the failing instruction's opcode or operands.
+    fn dispatch(
+        &mut self,
+        closure: &mut Rc<Closure>,
+        ip: &mut usize,
+        slots: &mut usize,
+    ) -> Result<Value, String> {
+        macro_rules! read_byte {
+            () => {{
+                let byte = closure.function.chunk.code[*ip];
+                *ip += 1;
+                byte
+            }};
+        }
+        macro_rules! read_u16 {
+            () => {{
+                let value = closure.function.chunk.read_u16(*ip);
+                *ip += 2;
+                value
+            }};
+        }
+        macro_rules! read_constant {
+            () => {
+                &closure.function.chunk.constants[read_u16!() as usize]
+            };
+        }
+        macro_rules! binary_number_op {
+            ($constructor:ident, $op:tt) => {{
+                let right = self.pop();
+                let left = self.pop();
+                match (left.unpack(), right.unpack()) {
+                    (Unpacked::Number(n1), Unpacked::Number(n2)) => {
+                        self.push(Value::$constructor(n1 $op n2))
+                    }
+                    _ => return Err("Operand must be numbers.".to_string()),
+                }
+            }};
+        }
+
+        loop {
+            if let Some(tracer) = &mut self.tracer {
+                trace_instruction(
+                    tracer,
+                    &closure.function,
+                    *ip,
+                    &self.stack,
+                    self.frames.len() - 1,
+                );
+            }
+            let op =
+                OpCode::from_byte(read_byte!()).ok_or_else(|| "Invalid opcode.".to_string())?;
+            match op {
+                OpCode::Constant => {
+                    let constant = read_constant!().clone();
+                    self.push(constant);
+                }
+                OpCode::Nil => self.push(Value::nil()),
+                OpCode::True => self.push(Value::boolean(true)),
+                OpCode::False => self.push(Value::boolean(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::Dup => self.push(self.peek(0).clone()),
+                OpCode::GetLocal => {
+                    let slot = read_byte!() as usize;
+                    self.push(self.stack[*slots + slot].clone());
+                }
+                OpCode::SetLocal => {
+                    let slot = read_byte!() as usize;
+                    self.stack[*slots + slot] = self.peek(0).clone();
+                }
+                OpCode::GetGlobal => {
+                    let Unpacked::String(name) = read_constant!().unpack() else {
+                        unreachable!("Global names are string constants")
+                    };
+                    match self.globals.get(name) {
+                        Some(value) => {
+                            let value = value.clone();
+                            self.push(value);
+                        }
+                        None => return Err(format!("Undefined variable '{}'.", name)),
+                    }
+                }
+                OpCode::DefineGlobal => {
+                    let Unpacked::String(name) = read_constant!().unpack() else {
+                        unreachable!("Global names are string constants")
+                    };
+                    let name = Rc::clone(name);
+                    let value = self.pop();
+                    self.globals.insert(name, value);
+                }
+                OpCode::SetGlobal => {
+                    let Unpacked::String(name) = read_constant!().unpack() else {
+                        unreachable!("Global names are string constants")
+                    };
+                    let value = self.peek(0).clone();
+                    match self.globals.get_mut(name) {
+                        Some(global) => *global = value,
+                        None => return Err(format!("Undefined variable '{}'.", name)),
+                    }
+                }
+                OpCode::GetUpvalue => {
+                    let index = read_byte!() as usize;
+                    let value = match *closure.upvalues[index].borrow() {
+                        Upvalue::Open(slot) => self.stack[slot].clone(),
+                        Upvalue::Closed(ref value) => value.clone(),
+                    };
+                    self.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let index = read_byte!() as usize;
+                    let value = self.peek(0).clone();
+                    match *closure.upvalues[index].borrow_mut() {
+                        Upvalue::Open(slot) => self.stack[slot] = value,
+                        Upvalue::Closed(ref mut closed) => *closed = value,
+                    }
+                }
+                OpCode::GetProperty => return Err("Only instances have properties.".to_string()),
+                OpCode::SetProperty => return Err("Only instances have fields.".to_string()),
+                OpCode::Equal => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.push(Value::boolean(left.equals(&right)));
+                }
+                OpCode::Greater => binary_number_op!(boolean, >),
+                OpCode::GreaterEqual => binary_number_op!(boolean, >=),
+                OpCode::Less => binary_number_op!(boolean, <),
+                OpCode::LessEqual => binary_number_op!(boolean, <=),
+                OpCode::Add => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    match (left.unpack(), right.unpack()) {
+                        (Unpacked::Number(n1), Unpacked::Number(n2)) => {
+                            self.push(Value::number(n1 + n2))
+                        }
+                        (Unpacked::String(s1), Unpacked::String(s2)) => {
+                            let mut s = String::with_capacity(s1.len() + s2.len());
+                            s.push_str(s1);
+                            s.push_str(s2);
+                            self.push(Value::string(s.into()))
+                        }
+                        _ => {
+                            return Err("Operand must be both numbers or both strings.".to_string())
+                        }
+                    }
+                }
+                OpCode::Subtract => binary_number_op!(number, -),
+                OpCode::Multiply => binary_number_op!(number, *),
+                OpCode::Divide => binary_number_op!(number, /),
+                OpCode::Modulo => binary_number_op!(number, %),
+                OpCode::Not => match self.pop().unpack() {
+                    Unpacked::Boolean(b) => self.push(Value::boolean(!b)),
+                    _ => return Err("Operand must be a Boolean.".to_string()),
+                },
+                OpCode::Negate => match self.pop().unpack() {
+                    Unpacked::Number(n) => self.push(Value::number(-n)),
+                    _ => return Err("Operand must be a number.".to_string()),
+                },
+                OpCode::Stringify => {
+                    let value = self.pop();
+                    self.push(Value::string(value.to_string().into()));
+                }
+                OpCode::Print => {
+                    let value = self.pop();
+                    if !value.is_nil() {
+                        println!("{}", value);
+                    }
+                }
+                OpCode::Jump => {
+                    let offset = read_u16!() as usize;
+                    *ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = read_u16!() as usize;
+                    if !self.peek(0).is_true() {
+                        *ip += offset;
+                    }
+                }
+                OpCode::JumpIfNotNil => {
+                    let offset = read_u16!() as usize;
+                    if !self.peek(0).is_nil() {
+                        *ip += offset;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = read_u16!() as usize;
+                    *ip -= offset;
+                }
+                OpCode::Call => {
+                    let arg_count = read_byte!() as usize;
+                    self.frames.last_mut().unwrap().ip = *ip;
+                    if self.call_value(arg_count)? {
+                        let frame = self.frames.last().unwrap();
+                        *closure = Rc::clone(&frame.closure);
+                        *ip = frame.ip;
+                        *slots = frame.slots;
+                    }
+                }
+                OpCode::Closure => {
+                    let Unpacked::Function(function) = read_constant!().unpack() else {
+                        unreachable!("Closures are built from function constants")
+                    };
+                    let function = Rc::clone(function);
+                    let mut upvalues = Vec::with_capacity(function.upvalue_count);
+                    for _ in 0..function.upvalue_count {
+                        let is_local = read_byte!() == 1;
+                        let index = read_byte!() as usize;
+                        if is_local {
+                            upvalues.push(self.capture_upvalue(*slots + index));
+                        } else {
+                            upvalues.push(Rc::clone(&closure.upvalues[index]));
+                        }
+                    }
+                    self.push(Value::closure(Rc::new(Closure { function, upvalues })));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    self.close_upvalues(*slots);
+                    self.frames.pop();
+                    self.stack.truncate(*slots);
+                    if self.frames.is_empty() {
+                        return Ok(result);
+                    }
+                    self.push(result);
+                    let frame = self.frames.last().unwrap();
+                    *closure = Rc::clone(&frame.closure);
+                    *ip = frame.ip;
+                    *slots = frame.slots;
+                }
+            }
+        }
+    }
+
+    /// Call the value sitting below `arg_count` arguments. Returns whether a
+    /// new frame was pushed.
+    fn call_value(&mut self, arg_count: usize) -> Result<bool, String> {
+        let callee_slot = self.stack.len() - arg_count - 1;
+        match self.stack[callee_slot].unpack() {
+            Unpacked::Closure(closure) => {
+                let closure = Rc::clone(closure);
+                check_arity(closure.function.arity, arg_count)?;
+                if self.frames.len() == FRAMES_MAX {
+                    return Err("Stack overflow.".to_string());
+                }
+                self.frames.push(CallFrame {
+                    closure,
+                    ip: 0,
+                    slots: callee_slot,
+                });
+                Ok(true)
+            }
+            Unpacked::Native(native) => {
+                let native = Rc::clone(native);
+                check_arity(native.arity, arg_count)?;
+                let result = (native.function)(&self.stack[callee_slot + 1..])?;
+                self.stack.truncate(callee_slot);
+                self.push(result);
+                Ok(false)
+            }
+            _ => Err("Can only call functions and classes.".to_string()),
+        }
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let position = self
+            .open_upvalues
+            .iter()
+            .rposition(|upvalue| matches!(*upvalue.borrow(), Upvalue::Open(s) if s <= slot));
+        if let Some(position) = position {
+            let upvalue = &self.open_upvalues[position];
+            if matches!(*upvalue.borrow(), Upvalue::Open(s) if s == slot) {
+                return Rc::clone(upvalue);
+            }
+        }
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+        let index = position.map_or(0, |p| p + 1);
+        self.open_upvalues.insert(index, Rc::clone(&upvalue));
+        upvalue
+    }
+
+    /// Move every captured variable at or above `last` off the stack.
+    fn close_upvalues(&mut self, last: usize) {
+        while let Some(upvalue) = self.open_upvalues.last() {
+            let slot = match *upvalue.borrow() {
+                Upvalue::Open(slot) if slot >= last => slot,
+                _ => break,
+            };
+            *upvalue.borrow_mut() = Upvalue::Closed(self.stack[slot].clone());
+            self.open_upvalues.pop();
+        }
+    }
+
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("Value stack underflow")
+    }
+
+    fn peek(&self, distance: usize) -> &Value {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+}
+
+fn trace_instruction(
+    tracer: &mut Tracer,
+    function: &Function,
+    ip: usize,
+    stack: &[Value],
+    depth: usize,
+) {
+    let name = function.name.as_deref().unwrap_or(trace::SCRIPT);
+    if !tracer.traces(name) {
+        return;
+    }
+    let mut event = format!("{:04} ", ip);
+    debug::describe_instruction(&function.chunk, ip, &mut event);
+    let stack: String = stack
+        .iter()
+        .map(|value| match value.unpack() {
+            Unpacked::String(s) => format!("[ {:?} ]", s),
+            _ => format!("[ {} ]", value),
+        })
+        .collect();
+    tracer.log(
+        function.chunk.lines[ip],
+        depth,
+        name,
+        event.trim_end(),
+        &stack,
+    );
+}
+
+fn check_arity(arity: usize, arg_count: usize) -> Result<(), String> {
+    if arity == arg_count {
+        Ok(())
+    } else {
+        Err(format!(
+            "Expected {} arguments but got {}.",
+            arity, arg_count
+        ))
+    }
+}
@@ -0,0 +1,194 @@
+use super::chunk::{Chunk, OpCode};
+use super::value::{Function, Unpacked};
+use std::fmt::Write;
+
+/// Disassemble `function` and, after it, every function nested inside it.
+pub fn disassemble(function: &Function) -> String {
+    let mut out = String::new();
+    disassemble_function(function, &mut out);
+    out
+}
+
+fn disassemble_function(function: &Function, out: &mut String) {
+    disassemble_chunk(&function.chunk, &function.to_string(), out);
+    for constant in &function.chunk.constants {
+        if let Unpacked::Function(nested) = constant.unpack() {
+            out.push('\n');
+            disassemble_function(nested, out);
+        }
+    }
+}
+
+/// Append a listing of every instruction in `chunk` to `out`.
+pub fn disassemble_chunk(chunk: &Chunk, name: &str, out: &mut String) {
+    writeln!(out, "== {} ==", name).unwrap();
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        offset = disassemble_instruction(chunk, offset, out);
+    }
+}
+
+/// Append the instruction at `offset` to `out` and return the offset of the
+/// next instruction.
+pub fn disassemble_instruction(chunk: &Chunk, offset: usize, out: &mut String) -> usize {
+    write!(out, "{:04} ", offset).unwrap();
+    if offset > 0 && chunk.lines[offset] == chunk.lines[offset - 1] {
+        write!(out, "   | ").unwrap();
+    } else {
+        write!(out, "{:4} ", chunk.lines[offset]).unwrap();
+    }
+    describe_instruction(chunk, offset, out)
+}
+
+/// Append the opcode name and decoded operands of the instruction at
+/// `offset` to `out` and return the offset of the next instruction.
+pub(super) fn describe_instruction(chunk: &Chunk, offset: usize, out: &mut String) -> usize {
+    let op = match OpCode::from_byte(chunk.code[offset]) {
+        Some(op) => op,
+        None => {
+            writeln!(out, "Unknown opcode {}", chunk.code[offset]).unwrap();
+            return offset + 1;
+        }
+    };
+    let name = format!("{:?}", op);
+    match op {
+        OpCode::Constant
+        | OpCode::GetGlobal
+        | OpCode::DefineGlobal
+        | OpCode::SetGlobal
+        | OpCode::GetProperty
+        | OpCode::SetProperty => {
+            let constant = chunk.read_u16(offset + 1);
+            writeln!(
+                out,
+                "{:<16} {:4} '{}'",
+                name, constant, chunk.constants[constant as usize]
+            )
+            .unwrap();
+            offset + 3
+        }
+        OpCode::GetLocal | OpCode::SetLocal | OpCode::GetUpvalue | OpCode::SetUpvalue => {
+            writeln!(out, "{:<16} {:4}", name, chunk.code[offset + 1]).unwrap();
+            offset + 2
+        }
+        OpCode::Call => {
+            writeln!(out, "{:<16} {:4} args", name, chunk.code[offset + 1]).unwrap();
+            offset + 2
+        }
+        OpCode::Jump | OpCode::JumpIfFalse | OpCode::JumpIfNotNil | OpCode::Loop => {
+            let jump = chunk.read_u16(offset + 1) as usize;
+            let target = if op == OpCode::Loop {
+                offset + 3 - jump
+            } else {
+                offset + 3 + jump
+            };
+            writeln!(out, "{:<16} {:4} -> {:04}", name, offset, target).unwrap();
+            offset + 3
+        }
+        OpCode::Closure => {
+            let constant = chunk.read_u16(offset + 1);
+            let value = &chunk.constants[constant as usize];
+            writeln!(out, "{:<16} {:4} {}", name, constant, value).unwrap();
+            let mut offset = offset + 3;
+            if let Unpacked::Function(function) = value.unpack() {
+                for _ in 0..function.upvalue_count {
+                    let kind = if chunk.code[offset] == 1 {
+                        "local"
+                    } else {
+                        "upvalue"
+                    };
+                    let index = chunk.code[offset + 1];
+                    writeln!(
+                        out,
+                        "{:04}    |                     {} {}",
+                        offset, kind, index
+                    )
+                    .unwrap();
+                    offset += 2;
+                }
+            }
+            offset
+        }
+        _ => {
+            writeln!(out, "{}", name).unwrap();
+            offset + 1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser, vm::Compiler};
+
+    fn dump(source: &str) -> String {
+        let tokens = Lexer::new(source).scan_tokens().unwrap();
+        let stmts = Parser::new(tokens, false).parse().unwrap();
+        disassemble(&Compiler::compile(&stmts).unwrap())
+    }
+
+    #[test]
+    fn constants_and_lines() {
+        assert_eq!(
+            dump("print 1 + 2;\nvar a = \"x\";"),
+            "\
+== <script> ==
+0000    1 Constant            0 '1'
+0003    | Constant            1 '2'
+0006    | Add
+0007    | Print
+0008    2 Constant            2 'x'
+0011    | DefineGlobal        3 'a'
+0014    | Nil
+0015    | Return
+"
+        );
+    }
+
+    #[test]
+    fn jumps_show_targets() {
+        assert_eq!(
+            dump("while true { print 1; }"),
+            "\
+== <script> ==
+0000    1 True
+0001    | JumpIfFalse         1 -> 0012
+0004    | Pop
+0005    | Constant            0 '1'
+0008    | Print
+0009    | Loop                9 -> 0000
+0012    | Pop
+0013    | Nil
+0014    | Return
+"
+        );
+    }
+
+    #[test]
+    fn nested_functions_and_upvalues() {
+        assert_eq!(
+            dump("fun outer(a) {\n  fun inner() { return a; }\n  return inner;\n}"),
+            "\
+== <script> ==
+0000    1 Closure             0 <fn outer>
+0003    | DefineGlobal        1 'outer'
+0006    | Nil
+0007    | Return
+
+== <fn outer> ==
+0000    2 Closure             0 <fn inner>
+0003    |                     local 1
+0005    3 GetLocal            2
+0007    | Return
+0008    | Nil
+0009    | Return
+
+== <fn inner> ==
+0000    2 GetUpvalue          0
+0002    | Return
+0003    | Nil
+0004    | Return
+"
+        );
+    }
+}
@@ -0,0 +1,14 @@
+mod chunk;
+mod compiler;
+mod core;
+mod debug;
+mod natives;
+mod serialize;
+mod value;
+
+pub use self::chunk::{Chunk, OpCode};
+pub use self::compiler::Compiler;
+pub use self::core::{Vm, FRAMES_MAX};
+pub use self::debug::{disassemble, disassemble_chunk, disassemble_instruction};
+pub use self::serialize::{deserialize, serialize, FORMAT_VERSION, MAGIC};
+pub use self::value::{Closure, Function, Native, Unpacked, Upvalue, Value};
@@ -0,0 +1,20 @@
+use super::value::{Unpacked, Value};
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub fn clock(_args: &[Value]) -> Result<Value, String> {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    Ok(Value::number(time as f64))
+}
+
+/// `arg(index)`: the script argument at `index`, or `nil` past the end.
+pub fn arg(args: &[Rc<str>], index: &Value) -> Value {
+    let arg = match index.unpack() {
+        Unpacked::Number(index) if index >= 0.0 && index.fract() == 0.0 => args.get(index as usize),
+        _ => None,
+    };
+    arg.map_or_else(Value::nil, |arg| Value::string(Rc::clone(arg)))
+}
@@ -0,0 +1,743 @@
+//! Binary format for compiled programs, so scripts can be shipped without
+//! their source.
+//!
+//! A file starts with the [`MAGIC`] bytes, the format version as a `u16`
+//! and a 64-bit FNV-1a checksum of everything after it. Then comes the
+//! top-level function:
+//!
+//! ```text
+//! function  := name arity:u32 upvalues:u32 code lines constants
+//! name      := 0 | 1 string
+//! code      := len:u32 byte*
+//! lines     := runs:u32 (line:u32 count:u32)*    run-length encoded, one line per code byte
+//! constants := len:u32 constant*
+//! constant  := 0 number:f64 | 1 string | 2 function
+//! string    := len:u32 utf8-byte*
+//! ```
+//!
+//! All integers are little-endian. Loading checks the header and checksum,
+//! that functions nest at most [`NESTING_MAX`] deep, and then verifies each
+//! function's bytecode: every instruction decodes and refers to constants,
+//! upvalues and local slots that exist, every jump lands on an instruction,
+//! and every path through the code keeps the stack at one depth per
+//! instruction without popping into the function's own slot or popping a
+//! slot a closure captured before `CloseUpvalue` or `Return` closes it. A
+//! file the VM accepts therefore cannot index outside its chunk or its stack
+//! frame.
+
+use super::chunk::{Chunk, OpCode};
+use super::compiler::NESTING_MAX;
+use super::value::{Function, Unpacked, Value};
+use crate::interner::Symbol;
+use std::rc::Rc;
+
+pub const MAGIC: [u8; 4] = *b"LOXC";
+
+/// Bumped whenever the layout or the instruction set changes.
+pub const FORMAT_VERSION: u16 = 2;
+
+const HEADER_LEN: usize = MAGIC.len() + 2 + 8;
+
+const NUMBER: u8 = 0;
+const STRING: u8 = 1;
+const FUNCTION: u8 = 2;
+
+/// Encode the top-level function returned by [`Compiler::compile`](super::Compiler::compile).
+pub fn serialize(function: &Function) -> Vec<u8> {
+    let mut payload = Vec::new();
+    write_function(function, &mut payload);
+    let mut out = Vec::with_capacity(HEADER_LEN + payload.len());
+    out.extend_from_slice(&MAGIC);
+    out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    out.extend_from_slice(&checksum(&payload).to_le_bytes());
+    out.extend_from_slice(&payload);
+    out
+}
+
+/// Decode and validate a file written by [`serialize`].
+pub fn deserialize(bytes: &[u8]) -> Result<Function, String> {
+    if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC {
+        return Err("Not a compiled Lox file.".to_string());
+    }
+    if bytes.len() < HEADER_LEN {
+        return Err("Truncated header.".to_string());
+    }
+    let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+    if version != FORMAT_VERSION {
+        return Err(format!(
+            "Compiled with format version {} but this build reads version {}; recompile the source.",
+            version, FORMAT_VERSION
+        ));
+    }
+    let expected = u64::from_le_bytes(bytes[6..HEADER_LEN].try_into().unwrap());
+    let payload = &bytes[HEADER_LEN..];
+    if checksum(payload) != expected {
+        return Err("Checksum mismatch; the file is corrupted.".to_string());
+    }
+    let mut reader = Reader {
+        bytes: payload,
+        position: 0,
+        depth: 0,
+    };
+    let function = reader.function()?;
+    if reader.position != payload.len() {
+        return Err(format!(
+            "Unexpected data after the program at byte {}.",
+            HEADER_LEN + reader.position
+        ));
+    }
+    Ok(function)
+}
+
+fn write_function(function: &Function, out: &mut Vec<u8>) {
+    match &function.name {
+        Some(name) => {
+            out.push(1);
+            write_string(name, out);
+        }
+        None => out.push(0),
+    }
+    write_u32(function.arity, out);
+    write_u32(function.upvalue_count, out);
+
+    let chunk = &function.chunk;
+    write_u32(chunk.code.len(), out);
+    out.extend_from_slice(&chunk.code);
+
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for &line in &chunk.lines {
+        match runs.last_mut() {
+            Some((last, count)) if *last == line => *count += 1,
+            _ => runs.push((line, 1)),
+        }
+    }
+    write_u32(runs.len(), out);
+    for (line, count) in runs {
+        write_u32(line, out);
+        write_u32(count, out);
+    }
+
+    write_u32(chunk.constants.len(), out);
+    for constant in &chunk.constants {
+        match constant.unpack() {
+            Unpacked::Number(n) => {
+                out.push(NUMBER);
+                out.extend_from_slice(&n.to_le_bytes());
+            }
+            Unpacked::String(s) => {
+                out.push(STRING);
+                write_string(s, out);
+            }
+            Unpacked::Function(function) => {
+                out.push(FUNCTION);
+                write_function(function, out);
+            }
+            other => unreachable!("The compiler never emits {:?} as a constant", other),
+        }
+    }
+}
+
+fn write_u32(n: usize, out: &mut Vec<u8>) {
+    let n = u32::try_from(n).expect("Chunk too large to serialize");
+    out.extend_from_slice(&n.to_le_bytes());
+}
+
+fn write_string(s: &str, out: &mut Vec<u8>) {
+    write_u32(s.len(), out);
+    out.extend_from_slice(s.as_bytes());
+}
+
+/// 64-bit FNV-1a.
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    /// Functions being read, counting the top-level script.
+    depth: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], String> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| {
+                format!(
+                    "Unexpected end of file at byte {}.",
+                    HEADER_LEN + self.position
+                )
+            })?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<usize, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()) as usize)
+    }
+
+    fn f64(&mut self) -> Result<f64, String> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.u32()?;
+        let position = self.position;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| format!("Invalid UTF-8 in string at byte {}.", HEADER_LEN + position))
+    }
+
+    fn function(&mut self) -> Result<Function, String> {
+        self.depth += 1;
+        if self.depth > NESTING_MAX {
+            return Err(format!("Functions nest more than {} deep.", NESTING_MAX));
+        }
+        let name = match self.u8()? {
+            0 => None,
+            1 => Some(self.string()?),
+            flag => return Err(format!("Invalid function name flag {}.", flag)),
+        };
+        let arity = self.u32()?;
+        let upvalue_count = self.u32()?;
+
+        let len = self.u32()?;
+        let code = self.take(len)?.to_vec();
+        let mut lines = Vec::with_capacity(code.len());
+        for _ in 0..self.u32()? {
+            let line = self.u32()?;
+            let count = self.u32()?;
+            if lines.len() + count > code.len() {
+                return Err("Line table is longer than the code.".to_string());
+            }
+            lines.extend(std::iter::repeat_n(line, count));
+        }
+        if lines.len() != code.len() {
+            return Err("Line table is shorter than the code.".to_string());
+        }
+
+        let count = self.u32()?;
+        let mut constants = Vec::with_capacity(count.min(self.bytes.len()));
+        for _ in 0..count {
+            constants.push(match self.u8()? {
+                NUMBER => Value::number(self.f64()?),
+                STRING => Value::string(Rc::clone(Symbol::intern(&self.string()?).as_rc())),
+                FUNCTION => Value::function(Rc::new(self.function()?)),
+                tag => return Err(format!("Unknown constant tag {}.", tag)),
+            });
+        }
+
+        let function = Function {
+            name,
+            arity,
+            upvalue_count,
+            chunk: Chunk {
+                code,
+                lines,
+                constants,
+            },
+        };
+        validate(&function).map_err(|e| format!("Invalid bytecode in {}: {}", function, e))?;
+        self.depth -= 1;
+        Ok(function)
+    }
+}
+
+/// Check that every instruction of `function` decodes and only refers to
+/// constants, upvalues and jump targets that exist, then [`verify_stack`].
+fn validate(function: &Function) -> Result<(), String> {
+    let chunk = &function.chunk;
+    let code = &chunk.code;
+    let operand = |offset: usize, len: usize| -> Result<(), String> {
+        if offset + len < code.len() {
+            Ok(())
+        } else {
+            Err(format!("instruction at {:04} is cut off", offset))
+        }
+    };
+    let constant = |offset: usize| -> Result<&Value, String> {
+        operand(offset, 2)?;
+        let index = chunk.read_u16(offset + 1) as usize;
+        chunk
+            .constants
+            .get(index)
+            .ok_or_else(|| format!("constant {} at {:04} does not exist", index, offset))
+    };
+    // The length of the instruction starting at each offset; zero inside
+    // an instruction.
+    let mut lengths = vec![0; code.len()];
+    let mut offset = 0;
+    while offset < code.len() {
+        let op = OpCode::from_byte(code[offset])
+            .ok_or_else(|| format!("unknown opcode {} at {:04}", code[offset], offset))?;
+        let len = match op {
+            OpCode::Constant => constant(offset).map(|_| 3)?,
+            OpCode::GetGlobal
+            | OpCode::DefineGlobal
+            | OpCode::SetGlobal
+            | OpCode::GetProperty
+            | OpCode::SetProperty => match constant(offset)?.unpack() {
+                Unpacked::String(_) => 3,
+                _ => return Err(format!("name at {:04} is not a string", offset)),
+            },
+            OpCode::GetUpvalue | OpCode::SetUpvalue => {
+                operand(offset, 1)?;
+                if code[offset + 1] as usize >= function.upvalue_count {
+                    return Err(format!("upvalue at {:04} does not exist", offset));
+                }
+                2
+            }
+            OpCode::GetLocal | OpCode::SetLocal | OpCode::Call => operand(offset, 1).map(|_| 2)?,
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::JumpIfNotNil | OpCode::Loop => {
+                operand(offset, 2)?;
+                if jump_target(chunk, offset).is_none_or(|target| target >= code.len()) {
+                    return Err(format!("jump at {:04} leaves the chunk", offset));
+                }
+                3
+            }
+            OpCode::Closure => {
+                let Unpacked::Function(nested) = constant(offset)?.unpack() else {
+                    return Err(format!("closure at {:04} is not a function", offset));
+                };
+                let len = 3 + 2 * nested.upvalue_count;
+                operand(offset, len - 1)?;
+                for pair in (offset + 3..offset + len).step_by(2) {
+                    let index = code[pair + 1] as usize;
+                    if code[pair] > 1 || (code[pair] == 0 && index >= function.upvalue_count) {
+                        return Err(format!(
+                            "closure at {:04} captures an invalid upvalue",
+                            offset
+                        ));
+                    }
+                }
+                len
+            }
+            _ => 1,
+        };
+        lengths[offset] = len;
+        offset += len;
+    }
+    if code.last().and_then(|&byte| OpCode::from_byte(byte)) != Some(OpCode::Return) {
+        return Err("chunk does not end with a return".to_string());
+    }
+    verify_stack(function, &lengths)
+}
+
+/// Where the jump at `offset` goes, if it stays at or after the start of the
+/// chunk.
+fn jump_target(chunk: &Chunk, offset: usize) -> Option<usize> {
+    let jump = chunk.read_u16(offset + 1) as usize;
+    if chunk.code[offset] == OpCode::Loop as u8 {
+        (offset + 3).checked_sub(jump)
+    } else {
+        Some(offset + 3 + jump)
+    }
+}
+
+/// The stack of a frame as the verifier sees it at one instruction: how
+/// many values it holds and which of their slots inner closures captured.
+#[derive(Clone)]
+struct Frame {
+    depth: usize,
+    /// Slots that may be captured, sorted. Each stays open until a
+    /// `CloseUpvalue` or `Return` closes it, so nothing else may pop it.
+    captured: Vec<usize>,
+}
+
+/// Follow every path through `function`, whose instructions have the
+/// `lengths` found by [`validate`], and check that each instruction finds the
+/// values it pops and the local slots it uses on the stack. The frame starts
+/// with the function itself and its arguments, and must never pop the
+/// function's slot or a slot captured by a closure that is still open. An
+/// instruction reached by several paths must see the same depth on each,
+/// and treats a slot as captured if any of them captured it, so that the
+/// checks hold whichever one runs.
+fn verify_stack(function: &Function, lengths: &[usize]) -> Result<(), String> {
+    let chunk = &function.chunk;
+    let code = &chunk.code;
+    let mut frames: Vec<Option<Frame>> = vec![None; code.len()];
+    let start = Frame {
+        depth: function.arity + 1,
+        captured: Vec::new(),
+    };
+    let mut pending = vec![(0, start)];
+    while let Some((offset, mut frame)) = pending.pop() {
+        if let Some(seen) = &frames[offset] {
+            if seen.depth != frame.depth {
+                return Err(format!(
+                    "stack depth at {:04} depends on the path taken",
+                    offset
+                ));
+            }
+            // A slot counts as captured if any path captured it.
+            let mut captured = seen.captured.clone();
+            for &slot in &frame.captured {
+                if let Err(position) = captured.binary_search(&slot) {
+                    captured.insert(position, slot);
+                }
+            }
+            if captured == seen.captured {
+                continue;
+            }
+            frame.captured = captured;
+        }
+        frames[offset] = Some(frame.clone());
+        let depth = frame.depth;
+        let op = OpCode::from_byte(code[offset]).unwrap();
+        let (pops, pushes) = match op {
+            OpCode::Constant
+            | OpCode::Nil
+            | OpCode::True
+            | OpCode::False
+            | OpCode::GetLocal
+            | OpCode::GetGlobal
+            | OpCode::GetUpvalue
+            | OpCode::Closure => (0, 1),
+            OpCode::Pop | OpCode::DefineGlobal | OpCode::Print | OpCode::CloseUpvalue => (1, 0),
+            OpCode::Dup => (1, 2),
+            OpCode::SetLocal
+            | OpCode::SetGlobal
+            | OpCode::SetUpvalue
+            | OpCode::GetProperty
+            | OpCode::Not
+            | OpCode::Negate
+            | OpCode::Stringify
+            | OpCode::JumpIfFalse
+            | OpCode::JumpIfNotNil => (1, 1),
+            OpCode::SetProperty
+            | OpCode::Equal
+            | OpCode::Greater
+            | OpCode::GreaterEqual
+            | OpCode::Less
+            | OpCode::LessEqual
+            | OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide
+            | OpCode::Modulo => (2, 1),
+            OpCode::Jump | OpCode::Loop => (0, 0),
+            OpCode::Call => (code[offset + 1] as usize + 1, 1),
+            OpCode::Return => (1, 0),
+        };
+        if pops >= depth {
+            return Err(format!("instruction at {:04} underflows the stack", offset));
+        }
+        // These only look at the values they count as popped and pushed back.
+        let peeks = matches!(
+            op,
+            OpCode::Dup
+                | OpCode::SetLocal
+                | OpCode::SetGlobal
+                | OpCode::SetUpvalue
+                | OpCode::JumpIfFalse
+                | OpCode::JumpIfNotNil
+        );
+        match op {
+            OpCode::CloseUpvalue => frame.captured.retain(|&slot| slot != depth - 1),
+            _ if peeks => {}
+            _ => {
+                if let Some(slot) = frame.captured.iter().find(|&&slot| slot >= depth - pops) {
+                    return Err(format!(
+                        "instruction at {:04} pops captured slot {} without closing it",
+                        offset, slot
+                    ));
+                }
+            }
+        }
+        match op {
+            OpCode::GetLocal | OpCode::SetLocal if code[offset + 1] as usize >= depth => {
+                return Err(format!("local at {:04} does not exist", offset));
+            }
+            OpCode::Closure => {
+                for pair in (offset + 3..offset + lengths[offset]).step_by(2) {
+                    if code[pair] == 0 {
+                        continue;
+                    }
+                    // A local function may capture itself, in the slot its
+                    // closure is about to be pushed to.
+                    let slot = code[pair + 1] as usize;
+                    if slot > depth {
+                        return Err(format!(
+                            "closure at {:04} captures an invalid upvalue",
+                            offset
+                        ));
+                    }
+                    if let Err(position) = frame.captured.binary_search(&slot) {
+                        frame.captured.insert(position, slot);
+                    }
+                }
+            }
+            _ => {}
+        }
+        frame.depth = depth - pops + pushes;
+        let next = offset + lengths[offset];
+        let successors = match op {
+            OpCode::Return => vec![],
+            OpCode::Jump | OpCode::Loop => vec![jump_target(chunk, offset).unwrap()],
+            OpCode::JumpIfFalse | OpCode::JumpIfNotNil => {
+                vec![next, jump_target(chunk, offset).unwrap()]
+            }
+            _ => vec![next],
+        };
+        for successor in successors {
+            if successor >= code.len() {
+                return Err(format!("instruction at {:04} runs off the chunk", offset));
+            }
+            if lengths[successor] == 0 {
+                return Err(format!("jump at {:04} lands inside an instruction", offset));
+            }
+            pending.push((successor, frame.clone()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::{disassemble, Compiler};
+    use crate::{lexer::Lexer, parser::Parser};
+
+    const PROGRAM: &str = "var greeting = \"hi\";
+fun outer(x) {
+  fun inner() { return x + 1.5; }
+  return inner;
+}
+print outer(2)();";
+
+    fn compile(source: &str) -> Function {
+        let tokens = Lexer::new(source).scan_tokens().unwrap();
+        let stmts = Parser::new(tokens, false).parse().unwrap();
+        Compiler::compile(&stmts).unwrap()
+    }
+
+    #[test]
+    fn round_trips_nested_functions() {
+        let function = compile(PROGRAM);
+        let loaded = deserialize(&serialize(&function)).unwrap();
+        assert_eq!(disassemble(&loaded), disassemble(&function));
+        assert_eq!(loaded.chunk.lines, function.chunk.lines);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert_eq!(
+            deserialize(b"print 1;").unwrap_err(),
+            "Not a compiled Lox file."
+        );
+        assert_eq!(deserialize(b"LOXC\x01").unwrap_err(), "Truncated header.");
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut bytes = serialize(&compile(PROGRAM));
+        bytes[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(deserialize(&bytes)
+            .unwrap_err()
+            .starts_with("Compiled with format version 3 but this build reads version 2"));
+    }
+
+    #[test]
+    fn rejects_corruption() {
+        let bytes = serialize(&compile(PROGRAM));
+        let mut flipped = bytes.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        assert_eq!(
+            deserialize(&flipped).unwrap_err(),
+            "Checksum mismatch; the file is corrupted."
+        );
+
+        // A truncated payload with a matching checksum still fails to parse.
+        let mut truncated = bytes[..bytes.len() - 3].to_vec();
+        let sum = checksum(&truncated[HEADER_LEN..]);
+        truncated[6..HEADER_LEN].copy_from_slice(&sum.to_le_bytes());
+        assert!(deserialize(&truncated)
+            .unwrap_err()
+            .starts_with("Unexpected end of file"));
+    }
+
+    #[test]
+    fn rejects_invalid_bytecode() {
+        let mut function = compile("print 1;");
+        function.chunk.code[2] = 7;
+        assert_eq!(
+            deserialize(&serialize(&function)).unwrap_err(),
+            "Invalid bytecode in <script>: constant 7 at 0000 does not exist"
+        );
+
+        let mut function = compile("print 1;");
+        function.chunk.code.pop();
+        function.chunk.lines.pop();
+        assert_eq!(
+            deserialize(&serialize(&function)).unwrap_err(),
+            "Invalid bytecode in <script>: chunk does not end with a return"
+        );
+    }
+
+    /// Load a script whose code is `code`, with `constants`.
+    fn load(code: &[u8], constants: Vec<Value>) -> Result<Function, String> {
+        let function = Function {
+            name: None,
+            arity: 0,
+            upvalue_count: 0,
+            chunk: Chunk {
+                code: code.to_vec(),
+                lines: vec![1; code.len()],
+                constants,
+            },
+        };
+        deserialize(&serialize(&function))
+    }
+
+    fn error(code: &[u8]) -> String {
+        let error = load(code, vec![Value::number(1.0)]).unwrap_err();
+        error["Invalid bytecode in <script>: ".len()..].to_string()
+    }
+
+    #[test]
+    fn rejects_unsafe_stack_use() {
+        use OpCode::*;
+        assert!(load(&[Nil as u8, GetLocal as u8, 1, Return as u8], vec![]).is_ok());
+        assert_eq!(
+            error(&[GetLocal as u8, 200, Return as u8]),
+            "local at 0000 does not exist"
+        );
+        assert_eq!(
+            error(&[Nil as u8, SetLocal as u8, 2, Return as u8]),
+            "local at 0001 does not exist"
+        );
+        assert_eq!(
+            error(&[Pop as u8, Nil as u8, Return as u8]),
+            "instruction at 0000 underflows the stack"
+        );
+        assert_eq!(
+            error(&[Nil as u8, Call as u8, 1, Return as u8]),
+            "instruction at 0001 underflows the stack"
+        );
+        assert_eq!(
+            error(&[Return as u8]),
+            "instruction at 0000 underflows the stack"
+        );
+    }
+
+    #[test]
+    fn rejects_inconsistent_control_flow() {
+        use OpCode::*;
+        // Jumps into the constant index of the next instruction.
+        assert_eq!(
+            error(&[Jump as u8, 0, 1, Constant as u8, 0, 0, Return as u8]),
+            "jump at 0000 lands inside an instruction"
+        );
+        // Skipping the `Nil` leaves `Return` with one value less.
+        assert_eq!(
+            error(&[True as u8, JumpIfFalse as u8, 0, 1, Nil as u8, Return as u8]),
+            "stack depth at 0005 depends on the path taken"
+        );
+    }
+
+    #[test]
+    fn rejects_popping_captured_slots() {
+        use OpCode::*;
+        let inner = Rc::new(Function {
+            name: Some("f".to_string()),
+            arity: 0,
+            upvalue_count: 1,
+            chunk: Chunk {
+                code: vec![Nil as u8, Return as u8],
+                lines: vec![1; 2],
+                constants: Vec::new(),
+            },
+        });
+        let constants = || {
+            vec![
+                Value::function(Rc::clone(&inner)),
+                Value::string(Rc::clone(Symbol::intern("f").as_rc())),
+            ]
+        };
+        let error = |code: &[u8]| {
+            let error = load(code, constants()).unwrap_err();
+            error["Invalid bytecode in <script>: ".len()..].to_string()
+        };
+        let define = [DefineGlobal as u8, 0, 1];
+
+        // The closure captures the slot it is pushed to, which defining the
+        // global then pops while it is still open.
+        let captures_itself = [
+            &[Closure as u8, 0, 0, 1, 1][..],
+            &define,
+            &[Nil as u8, Return as u8],
+        ];
+        assert_eq!(
+            error(&captures_itself.concat()),
+            "instruction at 0005 pops captured slot 1 without closing it"
+        );
+        let closed = [Closure as u8, 0, 0, 1, 1, CloseUpvalue as u8];
+        assert!(load(
+            &[&closed[..], &[Nil as u8, Return as u8]].concat(),
+            constants()
+        )
+        .is_ok());
+
+        let pops_local = [
+            &[Nil as u8, Closure as u8, 0, 0, 1, 1][..],
+            &define,
+            &[Pop as u8, Nil as u8, Return as u8],
+        ];
+        assert_eq!(
+            error(&pops_local.concat()),
+            "instruction at 0009 pops captured slot 1 without closing it"
+        );
+        let closes_local = [
+            &[Nil as u8, Closure as u8, 0, 0, 1, 1][..],
+            &define,
+            &[CloseUpvalue as u8, Nil as u8, Return as u8],
+        ];
+        assert!(load(&closes_local.concat(), constants()).is_ok());
+
+        assert_eq!(
+            error(&[Closure as u8, 0, 0, 1, 2, Return as u8]),
+            "closure at 0000 captures an invalid upvalue"
+        );
+    }
+
+    #[test]
+    fn rejects_captures_of_missing_locals() {
+        let mut function = compile("{ var a = 1; fun f() { return a; } }");
+        let pair = function
+            .chunk
+            .code
+            .iter()
+            .position(|&byte| byte == OpCode::Closure as u8)
+            .unwrap()
+            + 3;
+        assert_eq!(function.chunk.code[pair..pair + 2], [1, 1]);
+        function.chunk.code[pair + 1] = 9;
+        assert_eq!(
+            deserialize(&serialize(&function)).unwrap_err(),
+            "Invalid bytecode in <script>: closure at 0003 captures an invalid upvalue"
+        );
+    }
+
+    #[test]
+    fn rejects_deep_nesting() {
+        let mut function = compile("nil;");
+        for _ in 0..NESTING_MAX {
+            let nested = Rc::new(function);
+            function = compile("nil;");
+            function.chunk.constants.push(Value::function(nested));
+        }
+        assert_eq!(
+            deserialize(&serialize(&function)).unwrap_err(),
+            format!("Functions nest more than {} deep.", NESTING_MAX)
+        );
+    }
+}
@@ -0,0 +1,264 @@
+use super::chunk::Chunk;
+use crate::display;
+use std::cell::RefCell;
+use std::fmt::{Debug, Display};
+use std::rc::Rc;
+
+#[cfg(feature = "nan-boxing")]
+mod nan_boxed;
+#[cfg(not(feature = "nan-boxing"))]
+mod tagged;
+
+#[cfg(feature = "nan-boxing")]
+pub use self::nan_boxed::Value;
+#[cfg(not(feature = "nan-boxing"))]
+pub use self::tagged::Value;
+
+/// A borrowed view of a [`Value`], which is what the VM matches on.
+///
+/// `Value` itself is opaque because it has two representations: a tagged
+/// enum by default, or a single NaN-boxed `u64` with the `nan-boxing`
+/// feature. Both provide the same constructors and [`Value::unpack`], and
+/// everything else is written once on top of them.
+#[derive(Debug, Clone, Copy)]
+pub enum Unpacked<'a> {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    String(&'a Rc<str>),
+    Function(&'a Rc<Function>),
+    Closure(&'a Rc<Closure>),
+    Native(&'a Rc<Native>),
+}
+
+impl Value {
+    pub fn is_nil(&self) -> bool {
+        matches!(self.unpack(), Unpacked::Nil)
+    }
+
+    /// Truthiness, matching [`Type::is_true`](crate::interpreter::Type::is_true).
+    pub fn is_true(&self) -> bool {
+        match self.unpack() {
+            Unpacked::Number(n) => n.is_normal(),
+            Unpacked::Boolean(b) => b,
+            Unpacked::String(s) => !s.is_empty(),
+            _ => false,
+        }
+    }
+
+    /// Equality, matching the tree-walking interpreter's `==`.
+    pub fn equals(&self, other: &Value) -> bool {
+        match (self.unpack(), other.unpack()) {
+            (Unpacked::Number(n1), Unpacked::Number(n2)) => n1 == n2,
+            (Unpacked::String(s1), Unpacked::String(s2)) => Rc::ptr_eq(s1, s2) || s1 == s2,
+            (Unpacked::Boolean(b1), Unpacked::Boolean(b2)) => b1 == b2,
+            (Unpacked::Nil, Unpacked::Nil) => true,
+            _ => false,
+        }
+    }
+
+    /// The name of the value's type, as shown by the REPL.
+    pub fn type_name(&self) -> &'static str {
+        match self.unpack() {
+            Unpacked::Nil => "nil",
+            Unpacked::Boolean(_) => "boolean",
+            Unpacked::Number(_) => "number",
+            Unpacked::String(_) => "string",
+            Unpacked::Function(_) | Unpacked::Closure(_) | Unpacked::Native(_) => "function",
+        }
+    }
+
+    /// The value as the REPL echoes it; see [`display`] for the format.
+    pub fn repr(&self) -> String {
+        match self.unpack() {
+            Unpacked::String(s) => display::string_repr(s),
+            Unpacked::Function(function) => {
+                display::callable_repr(&function.to_string(), function.arity)
+            }
+            Unpacked::Closure(closure) => {
+                display::callable_repr(&closure.function.to_string(), closure.function.arity)
+            }
+            Unpacked::Native(native) => display::callable_repr(&self.to_string(), native.arity),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.unpack() {
+            Unpacked::Nil => write!(f, "nil"),
+            Unpacked::Boolean(b) => write!(f, "{}", b),
+            Unpacked::Number(n) => write!(f, "{}", display::number(n)),
+            Unpacked::String(s) => write!(f, "{}", s),
+            Unpacked::Function(function) => write!(f, "{}", function),
+            Unpacked::Closure(closure) => write!(f, "{}", closure.function),
+            Unpacked::Native(native) => write!(f, "{}", display::native(native.name)),
+        }
+    }
+}
+
+impl Debug for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&self.unpack(), f)
+    }
+}
+
+/// A compiled function body. The top-level script is a function without a
+/// name.
+#[derive(Debug, Default)]
+pub struct Function {
+    pub name: Option<String>,
+    pub arity: usize,
+    pub upvalue_count: usize,
+    pub chunk: Chunk,
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.name {
+            Some(ref name) => write!(f, "{}", display::function(name)),
+            None => write!(f, "<script>"),
+        }
+    }
+}
+
+/// A function together with the variables it captured.
+#[derive(Debug)]
+pub struct Closure {
+    pub function: Rc<Function>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+/// A captured variable. It points into the value stack while the variable
+/// is in scope and owns the value once the variable's scope has ended.
+#[derive(Debug)]
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+/// The Rust side of a native function, called with its arguments.
+pub type NativeFn = Box<dyn Fn(&[Value]) -> Result<Value, String>>;
+
+pub struct Native {
+    pub name: &'static str,
+    pub arity: usize,
+    pub function: NativeFn,
+}
+
+impl Debug for Native {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", display::native(self.name))
+    }
+}
+
+/// These run against whichever representation is compiled in; run them with
+/// and without `--features nan-boxing` to check both behave the same.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> Value {
+        Value::string(s.into())
+    }
+
+    #[test]
+    fn numbers_round_trip() {
+        for n in [
+            0.0,
+            -0.0,
+            1.5,
+            -2.0,
+            f64::MAX,
+            f64::MIN_POSITIVE,
+            f64::INFINITY,
+        ] {
+            match Value::number(n).unpack() {
+                Unpacked::Number(m) => assert_eq!(m.to_bits(), n.to_bits()),
+                other => panic!("Expected {} but got {:?}", n, other),
+            }
+        }
+        assert!(
+            matches!(Value::number((-1.0f64).sqrt()).unpack(), Unpacked::Number(n) if n.is_nan())
+        );
+    }
+
+    #[test]
+    fn singletons_and_strings_unpack() {
+        assert!(Value::nil().is_nil());
+        assert!(matches!(
+            Value::boolean(true).unpack(),
+            Unpacked::Boolean(true)
+        ));
+        assert!(matches!(
+            Value::boolean(false).unpack(),
+            Unpacked::Boolean(false)
+        ));
+        assert!(!Value::boolean(false).is_nil());
+        assert!(matches!(string("lox").clone().unpack(), Unpacked::String(s) if &**s == "lox"));
+        let function = Rc::new(Function::default());
+        let value = Value::function(Rc::clone(&function));
+        assert!(matches!(value.unpack(), Unpacked::Function(f) if Rc::ptr_eq(f, &function)));
+    }
+
+    #[test]
+    fn truthiness() {
+        assert!(Value::number(1.0).is_true());
+        assert!(!Value::number(0.0).is_true());
+        assert!(!Value::number(f64::NAN).is_true());
+        assert!(Value::boolean(true).is_true());
+        assert!(!Value::boolean(false).is_true());
+        assert!(string("a").is_true());
+        assert!(!string("").is_true());
+        assert!(!Value::nil().is_true());
+    }
+
+    #[test]
+    fn equality() {
+        assert!(Value::nil().equals(&Value::nil()));
+        assert!(Value::number(2.0).equals(&Value::number(2.0)));
+        assert!(Value::number(0.0).equals(&Value::number(-0.0)));
+        assert!(!Value::number(f64::NAN).equals(&Value::number(f64::NAN)));
+        assert!(string("ab").equals(&string("ab")));
+        assert!(!string("ab").equals(&string("ba")));
+        assert!(!Value::number(1.0).equals(&Value::boolean(true)));
+        assert!(!Value::nil().equals(&Value::boolean(false)));
+    }
+
+    #[test]
+    fn display() {
+        let function = Function {
+            name: Some("f".to_string()),
+            ..Function::default()
+        };
+        let values = [
+            Value::nil(),
+            Value::boolean(true),
+            Value::number(2.5),
+            Value::number(-0.0),
+            string("text"),
+            Value::function(Rc::new(function)),
+        ];
+        let shown: Vec<String> = values.iter().map(Value::to_string).collect();
+        assert_eq!(shown, ["nil", "true", "2.5", "-0", "text", "<fn f>"]);
+    }
+
+    #[test]
+    fn repr() {
+        let function = Function {
+            name: Some("f".to_string()),
+            arity: 2,
+            ..Function::default()
+        };
+        let values = [
+            Value::number(f64::NAN),
+            Value::number(-f64::INFINITY),
+            Value::number(3.0),
+            string("a\"b"),
+            Value::function(Rc::new(function)),
+        ];
+        let shown: Vec<String> = values.iter().map(Value::repr).collect();
+        assert_eq!(shown, ["nan", "-inf", "3", "\"a\\\"b\"", "<fn f/2>"]);
+    }
+}
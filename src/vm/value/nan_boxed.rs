@@ -0,0 +1,166 @@
+use super::{Closure, Function, Native, Unpacked};
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+/// Bits set in every value that is not a number: the exponent, the quiet
+/// NaN bit and one more so the NaNs produced by arithmetic stay numbers.
+const QNAN: u64 = 0x7ffc_0000_0000_0000;
+/// Set, together with `QNAN`, in values holding a heap pointer.
+const SIGN_BIT: u64 = 0x8000_0000_0000_0000;
+/// The low bits that hold a pointer. User-space addresses fit in 48 bits.
+const PAYLOAD: u64 = 0x0000_ffff_ffff_ffff;
+
+const NIL: u64 = QNAN | 1;
+const FALSE: u64 = QNAN | 2;
+const TRUE: u64 = QNAN | 3;
+
+/// What a heap pointer stored in a [`Value`] points to.
+enum Object {
+    String(Rc<str>),
+    Function(Rc<Function>),
+    Closure(Rc<Closure>),
+    Native(Rc<Native>),
+}
+
+/// A runtime value of the bytecode backend, NaN-boxed into 8 bytes.
+///
+/// Numbers are stored as their own bits. Everything else is a quiet NaN
+/// whose low bits hold either a tag for `nil`, `true` and `false`, or, when
+/// the sign bit is set too, a pointer obtained from [`Rc::into_raw`]. The
+/// value owns one strong reference to that object, taken again on clone and
+/// given back on drop.
+pub struct Value {
+    bits: u64,
+    /// Keeps the value `!Send` and `!Sync`, like the `Rc` it may hold.
+    _object: PhantomData<Rc<Object>>,
+}
+
+impl Value {
+    fn from_bits(bits: u64) -> Self {
+        Value {
+            bits,
+            _object: PhantomData,
+        }
+    }
+
+    pub fn nil() -> Self {
+        Value::from_bits(NIL)
+    }
+
+    pub fn boolean(b: bool) -> Self {
+        Value::from_bits(if b { TRUE } else { FALSE })
+    }
+
+    pub fn number(n: f64) -> Self {
+        // Any NaN the program computes is stored as the canonical one, so its
+        // payload can never be mistaken for a tag.
+        let n = if n.is_nan() { f64::NAN } else { n };
+        Value::from_bits(n.to_bits())
+    }
+
+    pub fn string(s: Rc<str>) -> Self {
+        Value::object(Object::String(s))
+    }
+
+    pub fn function(function: Rc<Function>) -> Self {
+        Value::object(Object::Function(function))
+    }
+
+    pub fn closure(closure: Rc<Closure>) -> Self {
+        Value::object(Object::Closure(closure))
+    }
+
+    pub fn native(native: Rc<Native>) -> Self {
+        Value::object(Object::Native(native))
+    }
+
+    fn object(object: Object) -> Self {
+        let pointer = Rc::into_raw(Rc::new(object)) as u64;
+        assert_eq!(
+            pointer & !PAYLOAD,
+            0,
+            "Heap pointer does not fit in 48 bits"
+        );
+        Value::from_bits(SIGN_BIT | QNAN | pointer)
+    }
+
+    fn is_object(&self) -> bool {
+        self.bits & (SIGN_BIT | QNAN) == SIGN_BIT | QNAN
+    }
+
+    fn as_object(&self) -> Option<*const Object> {
+        self.is_object()
+            .then_some((self.bits & PAYLOAD) as *const Object)
+    }
+
+    #[inline]
+    pub fn unpack(&self) -> Unpacked<'_> {
+        if self.bits & QNAN != QNAN {
+            return Unpacked::Number(f64::from_bits(self.bits));
+        }
+        if let Some(pointer) = self.as_object() {
+            // SAFETY: the pointer came from `Rc::into_raw` and this value owns
+            // a strong reference to it, so it lives at least as long as `self`.
+            return match unsafe { &*pointer } {
+                Object::String(s) => Unpacked::String(s),
+                Object::Function(function) => Unpacked::Function(function),
+                Object::Closure(closure) => Unpacked::Closure(closure),
+                Object::Native(native) => Unpacked::Native(native),
+            };
+        }
+        match self.bits {
+            NIL => Unpacked::Nil,
+            FALSE => Unpacked::Boolean(false),
+            TRUE => Unpacked::Boolean(true),
+            bits => unreachable!("Invalid NaN-boxed value {:#018x}", bits),
+        }
+    }
+}
+
+impl Clone for Value {
+    fn clone(&self) -> Self {
+        if let Some(pointer) = self.as_object() {
+            // SAFETY: `self` keeps the object alive; the new value owns the
+            // reference taken here.
+            unsafe { Rc::increment_strong_count(pointer) };
+        }
+        Value::from_bits(self.bits)
+    }
+}
+
+impl Drop for Value {
+    fn drop(&mut self) {
+        if let Some(pointer) = self.as_object() {
+            // SAFETY: gives back the reference this value owned.
+            unsafe { Rc::decrement_strong_count(pointer) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fits_in_eight_bytes() {
+        assert_eq!(std::mem::size_of::<Value>(), 8);
+    }
+
+    #[test]
+    fn nan_stays_a_number() {
+        for n in [f64::NAN, -f64::NAN, f64::from_bits(QNAN | 1)] {
+            assert!(matches!(Value::number(n).unpack(), Unpacked::Number(n) if n.is_nan()));
+        }
+    }
+
+    #[test]
+    fn objects_are_reference_counted() {
+        let s: Rc<str> = "shared".into();
+        let value = Value::string(Rc::clone(&s));
+        let copies = vec![value.clone(), value.clone()];
+        assert!(matches!(copies[1].unpack(), Unpacked::String(c) if Rc::ptr_eq(c, &s)));
+        drop(copies);
+        drop(value);
+        assert_eq!(Rc::strong_count(&s), 1);
+    }
+}
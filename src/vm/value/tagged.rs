@@ -0,0 +1,60 @@
+use super::{Closure, Function, Native, Unpacked};
+use std::rc::Rc;
+
+/// A runtime value of the bytecode backend, stored as a tagged enum.
+#[derive(Clone)]
+pub struct Value(Repr);
+
+#[derive(Clone)]
+enum Repr {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    String(Rc<str>),
+    Function(Rc<Function>),
+    Closure(Rc<Closure>),
+    Native(Rc<Native>),
+}
+
+impl Value {
+    pub fn nil() -> Self {
+        Value(Repr::Nil)
+    }
+
+    pub fn boolean(b: bool) -> Self {
+        Value(Repr::Boolean(b))
+    }
+
+    pub fn number(n: f64) -> Self {
+        Value(Repr::Number(n))
+    }
+
+    pub fn string(s: Rc<str>) -> Self {
+        Value(Repr::String(s))
+    }
+
+    pub fn function(function: Rc<Function>) -> Self {
+        Value(Repr::Function(function))
+    }
+
+    pub fn closure(closure: Rc<Closure>) -> Self {
+        Value(Repr::Closure(closure))
+    }
+
+    pub fn native(native: Rc<Native>) -> Self {
+        Value(Repr::Native(native))
+    }
+
+    #[inline]
+    pub fn unpack(&self) -> Unpacked<'_> {
+        match &self.0 {
+            Repr::Nil => Unpacked::Nil,
+            Repr::Boolean(b) => Unpacked::Boolean(*b),
+            Repr::Number(n) => Unpacked::Number(*n),
+            Repr::String(s) => Unpacked::String(s),
+            Repr::Function(function) => Unpacked::Function(function),
+            Repr::Closure(closure) => Unpacked::Closure(closure),
+            Repr::Native(native) => Unpacked::Native(native),
+        }
+    }
+}
@@ -0,0 +1,243 @@
+//! Runs the same programs on the tree-walking interpreter and the bytecode VM
+//! and checks that both print exactly the same thing. The tree-walker is also
+//! run with a collection on every allocation to catch objects freed too early.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+struct Output {
+    stdout: String,
+    stderr: String,
+}
+
+fn run(name: &str, source: &str, flags: &[&str]) -> Output {
+    let path: PathBuf =
+        std::env::temp_dir().join(format!("lox-backends-{}-{}.lox", name, std::process::id()));
+    std::fs::write(&path, source).unwrap();
+    let mut command = Command::new(env!("CARGO_BIN_EXE_lox-rs"));
+    command.arg(&path);
+    command.args(flags);
+    let output = command.output().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    Output {
+        stdout: String::from_utf8(output.stdout).unwrap(),
+        stderr: String::from_utf8(output.stderr).unwrap(),
+    }
+}
+
+/// Assert that both backends print `expected` and report the same errors,
+/// with and without the optimizer.
+fn check(name: &str, source: &str, expected: &str) {
+    let tree_walker = run(name, source, &[]);
+    let stress_gc = run(name, source, &["--stress-gc"]);
+    let vm = run(name, source, &["--vm"]);
+    let optimized = run(name, source, &["-O"]);
+    let optimized_vm = run(name, source, &["--vm", "-O"]);
+    assert_eq!(
+        tree_walker.stdout, expected,
+        "tree-walker output of `{}`",
+        name
+    );
+    assert_eq!(stress_gc.stdout, expected, "stress GC output of `{}`", name);
+    assert_eq!(vm.stdout, expected, "VM output of `{}`", name);
+    assert_eq!(optimized.stdout, expected, "optimized output of `{}`", name);
+    assert_eq!(
+        optimized_vm.stdout, expected,
+        "optimized VM output of `{}`",
+        name
+    );
+    assert_eq!(tree_walker.stderr, stress_gc.stderr, "errors of `{}`", name);
+    assert_eq!(tree_walker.stderr, vm.stderr, "errors of `{}`", name);
+    assert_eq!(tree_walker.stderr, optimized.stderr, "errors of `{}`", name);
+    assert_eq!(
+        tree_walker.stderr, optimized_vm.stderr,
+        "errors of `{}`",
+        name
+    );
+}
+
+#[test]
+fn arithmetic_and_comparison() {
+    check(
+        "arithmetic",
+        "print 1 + 2 * 3; print (1 + 2) * 3; print 7 % 4; print -2 / 4;
+         print 1 < 2; print 2 <= 1; print 3 > 3; print 3 >= 3;
+         print 1 == 1; print 1 != 1; print \"a\" == \"a\"; print nil == false;",
+        "7\n9\n3\n-0.5\ntrue\nfalse\nfalse\ntrue\ntrue\nfalse\ntrue\nfalse\n",
+    );
+}
+
+#[test]
+fn strings() {
+    check(
+        "strings",
+        "var name = \"lox\"; print \"hello \" + name; print \"${name} is ${1 + 2}\";
+         print \"lo\" + \"x\" == name; print name != \"lox\";",
+        "hello lox\nlox is 3\ntrue\nfalse\n",
+    );
+}
+
+#[test]
+fn scopes_and_shadowing() {
+    check(
+        "scopes",
+        "var a = \"global\";
+         {
+             var a = \"outer\";
+             { var a = a + \" inner\"; print a; }
+             print a;
+         }
+         print a;",
+        "outer inner\nouter\nglobal\n",
+    );
+}
+
+#[test]
+fn control_flow() {
+    check(
+        "control_flow",
+        "var i = 0;
+         while i < 3 { print i; i += 1; }
+         for var j = 0; j < 2; j = j + 1 { if j == 0 { print \"zero\"; } }
+         print true and \"and\"; print false or \"or\"; print nil and 1;
+         print 1 > 2 ? \"yes\" : \"no\"; print nil ?? \"fallback\";",
+        "0\n1\n2\nzero\nand\nor\nno\nfallback\n",
+    );
+}
+
+#[test]
+fn else_chains() {
+    check(
+        "else_chains",
+        "fun sign(n) {
+           if n < 0 return \"negative\";
+           else if n == 0 return \"zero\";
+           else return \"positive\";
+         }
+         print sign(-2); print sign(0); print sign(5);
+         if true if false print \"inner\"; else print \"dangling\";
+         if (1 > 2) { print \"then\"; } else { print \"else\"; }
+         for (var i = 0; i < 2; i = i + 1) print i;",
+        "negative\nzero\npositive\ndangling\nelse\n0\n1\n",
+    );
+}
+
+#[test]
+fn recursion() {
+    check(
+        "recursion",
+        include_str!("../lox-files/test.lox"),
+        "1\n1\n2\n3\n5\n8\n13\n21\n34\n55\n",
+    );
+}
+
+#[test]
+fn closures() {
+    check(
+        "closures",
+        "fun counter() {
+             var count = 0;
+             fun increment() { count += 1; return count; }
+             return increment;
+         }
+         var a = counter();
+         var b = counter();
+         print a(); print a(); print b();
+
+         fun pair() {
+             var shared = \"before\";
+             fun get() { return shared; }
+             fun set() { shared = \"after\"; }
+             set();
+             return get;
+         }
+         print pair()();",
+        "1\n2\n1\nafter\n",
+    );
+}
+
+#[test]
+fn nested_and_looped_closures() {
+    check(
+        "nested_closures",
+        "fun outer() {
+             var x = \"outside\";
+             fun middle() { fun inner() { x = x + \"!\"; return x; } return inner; }
+             return middle();
+         }
+         var f = outer();
+         print f(); print f();
+
+         var seen = \"\";
+         for var i = 0; i < 3; i = i + 1 {
+             var j = i;
+             fun show() { return j; }
+             seen = seen + \"${show()}\";
+         }
+         print seen;
+
+         fun sum(n) { fun go(k) { return k < 1 ? 0 : k + go(k - 1); } return go(n); }
+         print sum(10);",
+        "outside!\noutside!!\n012\n55\n",
+    );
+}
+
+#[test]
+fn functions_without_return_value() {
+    check(
+        "implicit_nil",
+        "fun noop() {} print noop() ?? \"nil\"; fun early(x) { if x { return; } print \"late\"; } early(false);",
+        "nil\nlate\n",
+    );
+}
+
+#[test]
+fn constant_and_dead_code() {
+    check(
+        "constant_and_dead_code",
+        "print 60 * 60 * 24; print \"${1 + 1}\" + \"!\"; print 0 / 0 == 0 / 0; print -(1 - 1);
+         if false { print \"never\"; } if \"\" { print \"empty\"; } if 2 > 1 print \"taken\";
+         while nil { print \"loop\"; }
+         for var i = 0; false; i = i + 1 { print i; }
+         fun f() { return \"early\"; print \"dead\"; } print f();
+         print nil ?? 1 + 1; print false or \"or\"; print 1 + \"a\";",
+        "86400\n2!\nfalse\n-0\ntaken\nearly\n2\nor\n",
+    );
+}
+
+#[test]
+fn value_display() {
+    check(
+        "value_display",
+        "fun fibo(n) { return n; } print fibo; print clock; print arg;
+         print 3.0; print -2.50; print 0 / 0; print 1 / 0; print -1 / 0; print 1e21;
+         print \"${fibo} ${clock} ${0 / 0} ${10 / 4}\";",
+        "<fn fibo>\n<native fn clock>\n<native fn arg>\n3\n-2.5\nnan\ninf\n-inf\n1000000000000000000000\n<fn fibo> <native fn clock> nan 2.5\n",
+    );
+}
+
+#[test]
+fn compound_field_assignment_evaluates_object_once() {
+    // Without classes the field read fails, but only after the object
+    // expression ran, and it runs once.
+    let source = "var calls = 0;
+         fun target() { calls += 1; print \"target ${calls}\"; return nil; }
+         target().x += 1;";
+    check("compound_field", source, "target 1\n");
+    let stderr = run("compound_field_error", source, &[]).stderr;
+    assert!(
+        stderr.starts_with("Only instances have properties."),
+        "stderr: {}",
+        stderr
+    );
+}
+
+#[test]
+fn runtime_errors() {
+    check("negate", "print 1; print -\"a\"; print 2;", "1\n");
+    check("undefined", "print x;", "");
+    check("arity", "fun f(a) {} f(1, 2);", "");
+    check("not_callable", "\"text\"();", "");
+    check("in_function", "fun f() { return 1 + nil; } print f();", "");
+    check("stack_overflow", "fun f() { return f(); } f();", "");
+}
@@ -0,0 +1,65 @@
+//! Runs `bench` on a small benchmark directory.
+
+use std::path::Path;
+use std::process::Command;
+
+fn bench(dir: &Path, flags: &[&str]) -> (String, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_lox-rs"))
+        .arg("bench")
+        .arg(dir)
+        .args(flags)
+        .output()
+        .unwrap();
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+/// The `vs baseline` column of each row of the report.
+fn ratios(report: &str) -> Vec<String> {
+    report
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().nth(4).map(str::to_string))
+        .collect()
+}
+
+#[test]
+fn reports_and_saves_baseline() {
+    let dir = std::env::temp_dir().join(format!("lox-bench-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("bench")).unwrap();
+    std::fs::write(
+        dir.join("bench/loop.lox"),
+        "var i = 0; while i < 100 { i = i + 1; }",
+    )
+    .unwrap();
+
+    let (stdout, stderr) = bench(&dir.join("bench"), &["--save-baseline"]);
+    assert_eq!(stderr, "");
+    assert!(stdout.starts_with("benchmark "));
+    let rows: Vec<Vec<&str>> = stdout
+        .lines()
+        .skip(1)
+        .take(2)
+        .map(|line| line.split_whitespace().take(2).collect())
+        .collect();
+    assert_eq!(rows, [["loop", "tree-walker"], ["loop", "vm"]]);
+    assert_eq!(ratios(&stdout)[..2], ["-", "-"]);
+    let baseline = std::fs::read_to_string(dir.join("bench/baseline.txt")).unwrap();
+    assert_eq!(baseline.lines().count(), 3);
+
+    let (stdout, _) = bench(&dir.join("bench"), &[]);
+    assert!(ratios(&stdout).iter().all(|ratio| ratio.ends_with('x')));
+
+    std::fs::write(dir.join("bench/broken.lox"), "print -nil;").unwrap();
+    let (_, stderr) = bench(&dir.join("bench"), &[]);
+    let (_, missing) = bench(&dir.join("missing"), &[]);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(stderr.starts_with("Benchmark 'broken' failed under tree-walker"));
+    assert!(
+        missing.ends_with("missing': no such file or directory.\n"),
+        "stderr: {}",
+        missing
+    );
+}
@@ -0,0 +1,201 @@
+//! Command-line behaviour of the `lox-rs` binary.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+struct Output {
+    stdout: String,
+    stderr: String,
+    status: i32,
+}
+
+fn lox(args: &[&str], stdin: &str) -> Output {
+    lox_with_state(&temp("state"), args, stdin)
+}
+
+/// Run with `state` as the XDG state directory, where the REPL keeps its
+/// history.
+fn lox_with_state(state: &Path, args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lox-rs"))
+        .args(args)
+        .env("XDG_STATE_HOME", state)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    Output {
+        stdout: String::from_utf8(output.stdout).unwrap(),
+        stderr: String::from_utf8(output.stderr).unwrap(),
+        status: output.status.code().unwrap(),
+    }
+}
+
+fn temp(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("lox-cli-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn inline_code_and_stdin() {
+    for backend in [&[][..], &["--vm"]] {
+        let inline = lox(&[backend, &["-e", "print 1 + 2;"]].concat(), "");
+        assert_eq!(inline.stdout, "3\n");
+        let stdin = lox(&[backend, &["run", "-"]].concat(), "print \"piped\";");
+        assert_eq!(stdin.stdout, "piped\n");
+    }
+}
+
+#[test]
+fn script_arguments() {
+    let source = "print argCount(); print arg(0); print arg(1); \
+                  print arg(2) ?? \"none\"; print arg(1.5) ?? \"none\";";
+    for backend in [&[][..], &["--vm"]] {
+        let output = lox(
+            &[backend, &["-e", source, "--", "first", "--vm x"]].concat(),
+            "",
+        );
+        assert_eq!(output.stdout, "2\nfirst\n--vm x\nnone\nnone\n");
+    }
+    let none = lox(&["-e", "print argCount();"], "");
+    assert_eq!(none.stdout, "0\n");
+}
+
+#[test]
+fn help_and_version() {
+    let help = lox(&["--help"], "");
+    assert_eq!(help.status, 0);
+    assert!(help.stdout.starts_with("Usage: lox-rs"));
+    let version = lox(&["-V"], "");
+    assert_eq!(
+        version.stdout,
+        format!("lox-rs {}\n", env!("CARGO_PKG_VERSION"))
+    );
+}
+
+#[test]
+fn check_reports_errors_without_running() {
+    let ok = lox(&["check", "-"], "print \"not run\";");
+    assert_eq!(ok.status, 0);
+    assert_eq!(ok.stdout, "");
+    let bad = lox(&["check", "-e", "print (1;"], "");
+    assert_eq!(bad.status, 65);
+    assert_ne!(bad.stderr, "");
+
+    // Only the bytecode compiler limits a function to 256 locals.
+    let locals: String = (0..300).map(|i| format!("var a{}; ", i)).collect();
+    let many_locals = format!("fun f() {{ {}}}", locals);
+    assert_eq!(lox(&["check", "-e", &many_locals], "").status, 0);
+    assert_eq!(lox(&["-e", &many_locals], "").status, 0);
+    let on_vm = lox(&["check", "--vm", "-e", &many_locals], "");
+    assert_eq!(on_vm.status, 65);
+    assert!(on_vm
+        .stderr
+        .contains("Too many local variables in function."));
+}
+
+#[test]
+fn rejects_bad_usage() {
+    let two_files = lox(&["a.lox", "b.lox"], "");
+    assert_eq!(two_files.status, 64);
+    assert!(two_files.stderr.starts_with("Unexpected argument 'b.lox'."));
+    let unknown = lox(&["--fast"], "");
+    assert_eq!(unknown.status, 64);
+    assert!(unknown.stderr.starts_with("Unknown option '--fast'."));
+    let missing = lox(&["run", "does-not-exist.lox"], "");
+    assert_eq!(missing.status, 66);
+    assert_eq!(
+        missing.stderr,
+        "Could not read 'does-not-exist.lox': no such file or directory.\n"
+    );
+}
+
+#[test]
+fn exit_statuses() {
+    for backend in [&[][..], &["--vm"]] {
+        let status = |source: &str| lox(&[backend, &["-e", source]].concat(), "").status;
+        assert_eq!(status("print 1;"), 0);
+        assert_eq!(status("print (1;"), 65);
+        assert_eq!(status("print \"unterminated;"), 65);
+        assert_eq!(status("print nil + 1;"), 70);
+        assert_eq!(status("fun f() { return g(); } f();"), 70);
+    }
+    let directory = lox(&["run", "src"], "");
+    assert_eq!(directory.status, 66);
+    let compiled = lox(&["run", "-"], "LOXC garbage");
+    assert_eq!(compiled.status, 65);
+    let no_output = lox(&["compile", "-e", "print 1;"], "");
+    assert_eq!(no_output.status, 64);
+}
+
+#[test]
+fn repl_reads_statements_over_several_lines() {
+    let input =
+        "fun f(a,\n  b) {\n  return \"${a}\n${b}\";\n}\nprint f(1,\n2);\n/* a\ncomment */ 3\n";
+    for backend in [&["repl"][..], &["repl", "--vm"]] {
+        let output = lox(backend, input);
+        assert_eq!(output.stdout, "1\n2\n=> 3 : number\n");
+        assert_eq!(output.stderr, "");
+    }
+}
+
+#[test]
+fn repl_meta_commands() {
+    let script = temp("script.lox");
+    std::fs::write(&script, "var loaded = 1;\nprint \"loaded\";\n").unwrap();
+    let input = format!(
+        ":load {}\nvar name = \"lox\";\n:env\n:ast -a * (b + 1)\n:reset\n:env\n:bogus\n:quit\nprint 1;\n",
+        script.display()
+    );
+    for backend in [&["repl"][..], &["repl", "--vm"]] {
+        let output = lox(backend, &input);
+        let globals =
+            "arg = <native fn arg/1>\nargCount = <native fn argCount/0>\nclock = <native fn clock/0>\n";
+        assert_eq!(
+            output.stdout,
+            format!(
+                "loaded\n{}loaded = 1\nname = \"lox\"\n(* (- a) (group (+ b 1)))\n{}",
+                globals, globals
+            )
+        );
+        assert_eq!(
+            output.stderr,
+            "Unknown command ':bogus'. Type :help for a list.\n"
+        );
+    }
+    std::fs::remove_file(&script).unwrap();
+}
+
+#[test]
+fn repl_keeps_history() {
+    let state = temp("history");
+    let history = state.join("lox-rs").join("history");
+    lox_with_state(&state, &["repl"], "var a = 1;\nfun f() {\n}\n:env\n");
+    lox_with_state(&state, &["repl"], "print a;\n");
+    let saved = std::fs::read_to_string(&history).unwrap();
+    let entries: Vec<&str> = saved.lines().skip(1).collect();
+    assert_eq!(entries, ["var a = 1;", "fun f() {\\n}", ":env", "print a;"]);
+    std::fs::remove_dir_all(&state).unwrap();
+}
+
+#[test]
+fn repl_echoes_expression_values() {
+    let input =
+        "fun fibo(n) { return n; }\nfibo\n1 + 2;\nvar s = \"a\\nb\";\ns\nprint s;\nnil; s == s\n";
+    for backend in [&["repl"][..], &["repl", "--vm"]] {
+        let output = lox(backend, input);
+        assert_eq!(
+            output.stdout,
+            "=> <fn fibo/1>\n=> 3 : number\n=> \"a\\nb\" : string\na\nb\n=> nil : nil\n=> true : boolean\n"
+        );
+    }
+    let file = lox(&["-e", "1 + 2; print nil;"], "");
+    assert_eq!(file.stdout, "");
+}
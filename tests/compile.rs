@@ -0,0 +1,120 @@
+//! Compiles scripts to `.loxc` files and runs them.
+
+use lox_rs::vm::{serialize, Chunk, Function, OpCode};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const PROGRAM: &str = "fun counter() {
+  var count = 0;
+  fun increment() { count = count + 1; return count; }
+  return increment;
+}
+var next = counter();
+next();
+print \"count: ${next()}\";
+";
+
+fn lox(args: &[&Path]) -> (String, String) {
+    let (stdout, stderr, _) = lox_status(args);
+    (stdout, stderr)
+}
+
+fn lox_status(args: &[&Path]) -> (String, String, i32) {
+    let output = Command::new(env!("CARGO_BIN_EXE_lox-rs"))
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+        output.status.code().unwrap(),
+    )
+}
+
+fn temp(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("lox-compile-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn compiled_file_runs_like_its_source() {
+    let source = temp("counter.lox");
+    let compiled = temp("out.loxc");
+    std::fs::write(&source, PROGRAM).unwrap();
+    let compile = lox(&[Path::new("compile"), &source, Path::new("-o"), &compiled]);
+    assert_eq!(compile, (String::new(), String::new()));
+    let run = lox(&[Path::new("run"), &compiled]);
+    let from_source = lox(&[&source]);
+
+    // Without `-o` the output goes next to the source.
+    lox(&[Path::new("compile"), &source]);
+    let default_output = source.with_extension("loxc");
+    let exists = default_output.exists();
+
+    for path in [&source, &compiled, &default_output] {
+        let _ = std::fs::remove_file(path);
+    }
+    assert_eq!(run, ("count: 2\n".to_string(), String::new()));
+    assert_eq!(run, from_source);
+    assert!(exists);
+}
+
+#[test]
+fn rejects_invalid_files() {
+    let source = temp("valid.lox");
+    let compiled = temp("valid.loxc");
+    let garbage = temp("garbage.loxc");
+    std::fs::write(&source, PROGRAM).unwrap();
+    std::fs::write(&garbage, "print 1;").unwrap();
+    lox(&[Path::new("compile"), &source, Path::new("-o"), &compiled]);
+
+    let mut bytes = std::fs::read(&compiled).unwrap();
+    bytes[4] = bytes[4].wrapping_add(1);
+    let old_version = temp("old.loxc");
+    std::fs::write(&old_version, &bytes).unwrap();
+
+    let not_compiled = lox(&[Path::new("run"), &garbage]);
+    let wrong_version = lox(&[Path::new("run"), &old_version]);
+    let missing = lox(&[Path::new("run"), &temp("missing.loxc")]);
+    for path in [&source, &compiled, &garbage, &old_version] {
+        std::fs::remove_file(path).unwrap();
+    }
+
+    assert_eq!(not_compiled.0, "");
+    assert!(not_compiled.1.ends_with(": Not a compiled Lox file.\n"));
+    assert!(wrong_version.1.contains("Compiled with format version 3"));
+    assert!(missing.1.starts_with("Could not read"));
+}
+
+#[test]
+fn rejects_bytecode_reaching_outside_its_frame() {
+    // Well-formed instructions, but slot 200 is far past the stack.
+    let code = vec![
+        OpCode::GetLocal as u8,
+        200,
+        OpCode::Print as u8,
+        OpCode::Nil as u8,
+        OpCode::Return as u8,
+    ];
+    let function = Function {
+        name: None,
+        arity: 0,
+        upvalue_count: 0,
+        chunk: Chunk {
+            lines: vec![1; code.len()],
+            code,
+            constants: Vec::new(),
+        },
+    };
+    let crafted = temp("crafted.loxc");
+    std::fs::write(&crafted, serialize(&function)).unwrap();
+    let (stdout, stderr, status) = lox_status(&[Path::new("run"), &crafted]);
+    std::fs::remove_file(&crafted).unwrap();
+
+    assert_eq!(stdout, "");
+    assert!(
+        stderr.ends_with(": Invalid bytecode in <script>: local at 0000 does not exist\n"),
+        "stderr: {}",
+        stderr
+    );
+    assert_eq!(status, 65);
+}
@@ -0,0 +1,72 @@
+//! Runs programs on the tree-walking interpreter to check closures, the
+//! truthiness of conditions and call arity.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+fn run(name: &str, source: &str) -> (String, String) {
+    let path: PathBuf = std::env::temp_dir().join(format!(
+        "lox-interpreter-{}-{}.lox",
+        name,
+        std::process::id()
+    ));
+    std::fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_lox-rs"))
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn functions_close_over_their_defining_scope() {
+    let (stdout, stderr) = run(
+        "closures",
+        "fun makeCounter() {
+           var count = 0;
+           fun increment() { count = count + 1; return count; }
+           return increment;
+         }
+         var first = makeCounter();
+         var second = makeCounter();
+         print first(); print first(); print second();",
+    );
+    assert_eq!(stderr, "");
+    assert_eq!(stdout, "1\n2\n1\n");
+}
+
+#[test]
+fn conditions_use_truthiness() {
+    let (stdout, stderr) = run(
+        "truthiness",
+        "if nil print \"nil\";
+         if 0 print \"zero\";
+         if \"\" print \"empty string\";
+         if 2 print \"number\";
+         if \"s\" print \"string\";
+         if true print \"true\";",
+    );
+    assert_eq!(stderr, "");
+    assert_eq!(stdout, "number\nstring\ntrue\n");
+}
+
+#[test]
+fn calls_check_arity() {
+    let (stdout, stderr) = run(
+        "arity",
+        "fun pair(a, b) { return a; }
+         print pair(1, 2);
+         pair(1);
+         print \"unreachable\";",
+    );
+    assert_eq!(stdout, "1\n");
+    assert!(
+        stderr.contains("Expected 2 arguments but got 1."),
+        "stderr: {}",
+        stderr
+    );
+}
@@ -0,0 +1,44 @@
+//! Checks each malformed program in `tests/malformed` and compares the
+//! diagnostics with the `.err` file next to it. Set `LOX_BLESS=1` to rewrite
+//! the `.err` files from the current output.
+
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn diagnostics_match_golden_files() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/malformed");
+    let mut programs: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "lox"))
+        .collect();
+    programs.sort();
+    assert!(!programs.is_empty(), "no programs in {}", dir.display());
+
+    let bless = std::env::var_os("LOX_BLESS").is_some();
+    let mut mismatches = Vec::new();
+    for program in &programs {
+        let output = Command::new(env!("CARGO_BIN_EXE_lox-rs"))
+            .arg("check")
+            .arg(program)
+            .output()
+            .unwrap();
+        let name = program.file_name().unwrap().to_string_lossy();
+        assert_eq!(output.status.code(), Some(65), "status of {}", name);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        let golden = program.with_extension("err");
+        if bless {
+            std::fs::write(&golden, &stderr).unwrap();
+            continue;
+        }
+        let expected = std::fs::read_to_string(&golden).unwrap_or_default();
+        if stderr != expected {
+            mismatches.push(format!(
+                "{}:\n--- expected\n{}--- found\n{}",
+                name, expected, stderr
+            ));
+        }
+    }
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}
@@ -0,0 +1,173 @@
+//! Runs every `.lox` script under `tests/scripts` on both backends and checks
+//! it against the expectations written in its comments, in the format of the
+//! craftinginterpreters test suite:
+//!
+//! - `// expect: 3` is the next line the script prints.
+//! - `// expect runtime error: Operand must be a number.` means the script
+//!   stops with this error, reported on the comment's line, and exit status
+//!   70.
+//! - `// [line 4] Error at ';': Expect expression.` is a compile error, which
+//!   makes the script fail with exit status 65 before it runs. Written as
+//!   `// Error at ...` it is reported on the comment's own line.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const BACKENDS: [(&str, &[&str]); 2] = [("tree-walker", &[]), ("vm", &["--vm"])];
+
+#[derive(Debug, Default)]
+struct Expectations {
+    output: Vec<String>,
+    compile_errors: Vec<String>,
+    /// The message of the runtime error and the `[line N]` following it.
+    runtime_error: Option<[String; 2]>,
+}
+
+impl Expectations {
+    fn parse(source: &str) -> Self {
+        let mut expectations = Self::default();
+        for (number, line) in source.lines().enumerate() {
+            let Some((_, comment)) = line.split_once("// ") else {
+                continue;
+            };
+            if let Some(output) = comment.strip_prefix("expect: ") {
+                expectations.output.push(output.to_string());
+            } else if let Some(error) = comment.strip_prefix("expect runtime error: ") {
+                let line = format!("[line {}]", number + 1);
+                expectations.runtime_error = Some([error.to_string(), line]);
+            } else if comment.starts_with("[line ") {
+                expectations.compile_errors.push(comment.to_string());
+            } else if comment.starts_with("Error") {
+                let error = format!("[line {}] {}", number + 1, comment);
+                expectations.compile_errors.push(error);
+            }
+        }
+        expectations
+    }
+
+    fn status(&self) -> i32 {
+        if !self.compile_errors.is_empty() {
+            65
+        } else if self.runtime_error.is_some() {
+            70
+        } else {
+            0
+        }
+    }
+}
+
+/// Every `.lox` file under `dir` and its subdirectories, sorted by path.
+fn scripts(dir: &Path) -> Vec<PathBuf> {
+    let mut scripts = Vec::new();
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            scripts.extend(self::scripts(&path));
+        } else if path.extension().is_some_and(|extension| extension == "lox") {
+            scripts.push(path);
+        }
+    }
+    scripts.sort();
+    scripts
+}
+
+/// Run `script` with the backend `flags` and describe how it missed its
+/// expectations, if it did.
+fn check(script: &Path, flags: &[&str], expectations: &Expectations) -> Result<(), String> {
+    let output = Command::new(env!("CARGO_BIN_EXE_lox-rs"))
+        .arg(script)
+        .args(flags)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let stdout: Vec<&str> = stdout.lines().collect();
+    let stderr: Vec<&str> = stderr.lines().collect();
+
+    let mut failures = Vec::new();
+    if stdout != expectations.output {
+        failures.push(format!(
+            "expected output {:?}, found {:?}",
+            expectations.output, stdout
+        ));
+    }
+    let (expected_errors, errors_match) = match &expectations.runtime_error {
+        _ if !expectations.compile_errors.is_empty() => (
+            expectations.compile_errors.clone(),
+            stderr == expectations.compile_errors,
+        ),
+        Some(error) => (error.to_vec(), stderr == error),
+        None => (Vec::new(), stderr.is_empty()),
+    };
+    if !errors_match {
+        failures.push(format!(
+            "expected errors {:?}, found {:?}",
+            expected_errors, stderr
+        ));
+    }
+    let status = output.status.code().unwrap();
+    if status != expectations.status() {
+        failures.push(format!(
+            "expected exit status {}, found {}",
+            expectations.status(),
+            status
+        ));
+    }
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(failures.join("\n  "))
+    }
+}
+
+#[test]
+fn scripts_meet_their_expectations() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scripts");
+    let scripts = scripts(&dir);
+    assert!(!scripts.is_empty(), "no scripts in {}", dir.display());
+
+    let mut failures = Vec::new();
+    for script in &scripts {
+        let expectations = Expectations::parse(&std::fs::read_to_string(script).unwrap());
+        for (backend, flags) in BACKENDS {
+            if let Err(failure) = check(script, flags, &expectations) {
+                let name = script.strip_prefix(&dir).unwrap().display();
+                failures.push(format!("{} on the {}:\n  {}", name, backend, failure));
+            }
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} runs failed:\n{}",
+        failures.len(),
+        scripts.len() * BACKENDS.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn expectations_follow_the_craftinginterpreters_format() {
+    let expectations = Expectations::parse(
+        "print 1; // expect: 1\n\
+         print; // Error at ';': Expect expression.\n\
+         // [line 7] Error at end: Expect '}' after block.\n\
+         -nil; // expect runtime error: Operand must be a number.\n\
+         // a plain comment",
+    );
+    assert_eq!(expectations.output, ["1"]);
+    assert_eq!(
+        expectations.compile_errors,
+        [
+            "[line 2] Error at ';': Expect expression.",
+            "[line 7] Error at end: Expect '}' after block."
+        ]
+    );
+    assert_eq!(
+        expectations.runtime_error,
+        Some([
+            "Operand must be a number.".to_string(),
+            "[line 4]".to_string()
+        ])
+    );
+    assert_eq!(expectations.status(), 65);
+}
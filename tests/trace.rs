@@ -0,0 +1,79 @@
+//! Checks the execution log written by `--trace` on both backends.
+
+use std::process::Command;
+
+const PROGRAM: &str = "fun add(a, b) {
+  var sum = a + b;
+  return sum;
+}
+print add(1, 2);
+";
+
+/// Run `PROGRAM` with `flags` and return its stdout and stderr.
+fn trace(name: &str, flags: &[&str]) -> (String, String) {
+    let path = std::env::temp_dir().join(format!("lox-trace-{}-{}.lox", name, std::process::id()));
+    std::fs::write(&path, PROGRAM).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_lox-rs"))
+        .arg(&path)
+        .args(flags)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn tree_walker_logs_statements_and_bindings() {
+    let (stdout, stderr) = trace("tree_walker", &["--trace"]);
+    assert_eq!(stdout, "3\n");
+    assert_eq!(
+        stderr,
+        "\
+[line 1] depth 0 in <script>: (fun add)
+    arg = <native fn arg>, argCount = <native fn argCount>, clock = <native fn clock>
+[line 5] depth 0 in <script>: (print (call add 1 2))
+    add = <fn add>, arg = <native fn arg>, argCount = <native fn argCount>, clock = <native fn clock>
+[line 2] depth 1 in add: (var sum (+ a b))
+    a = 1, b = 2
+[line 3] depth 1 in add: (return sum)
+    a = 1, b = 2, sum = 3
+"
+    );
+}
+
+#[test]
+fn vm_logs_instructions_and_stack() {
+    let (stdout, stderr) = trace("vm", &["--vm", "--trace", "--trace-fn=add"]);
+    assert_eq!(stdout, "3\n");
+    assert_eq!(
+        stderr,
+        "\
+[line 2] depth 1 in add: 0000 GetLocal            1
+    [ <script> ][ <fn add> ][ 1 ][ 2 ]
+[line 2] depth 1 in add: 0002 GetLocal            2
+    [ <script> ][ <fn add> ][ 1 ][ 2 ][ 1 ]
+[line 2] depth 1 in add: 0004 Add
+    [ <script> ][ <fn add> ][ 1 ][ 2 ][ 1 ][ 2 ]
+[line 3] depth 1 in add: 0005 GetLocal            3
+    [ <script> ][ <fn add> ][ 1 ][ 2 ][ 3 ]
+[line 3] depth 1 in add: 0007 Return
+    [ <script> ][ <fn add> ][ 1 ][ 2 ][ 3 ][ 3 ]
+"
+    );
+}
+
+#[test]
+fn trace_file_and_function_filter() {
+    let log = std::env::temp_dir().join(format!("lox-trace-{}.log", std::process::id()));
+    let file_flag = format!("--trace-file={}", log.display());
+    let (stdout, stderr) = trace("file", &[&file_flag, "--trace-fn=<script>"]);
+    let written = std::fs::read_to_string(&log).unwrap();
+    std::fs::remove_file(&log).unwrap();
+    assert_eq!(stdout, "3\n");
+    assert_eq!(stderr, "");
+    assert_eq!(written.lines().count(), 4);
+    assert!(written.lines().all(|line| !line.contains(" in add:")));
+}